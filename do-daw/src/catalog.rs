@@ -0,0 +1,383 @@
+//! the plugins installed on this machine, remembered in sqlite so loading one doesn't mean
+//! rescanning every VST3 on the SD card.
+
+use log::*;
+use pyo3::{
+    exceptions::{PyLookupError, PyRuntimeError},
+    prelude::*,
+};
+use rack::prelude::{PluginInfo, PluginType, Scanner};
+use rusqlite::{Connection, params};
+use std::{
+    env,
+    error::Error,
+    fmt::{self, Display},
+    fs::create_dir_all,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+/// VST3 files subcategories the same way.
+const CATEGORY_SEPARATOR: &str = "|";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS plugins (
+    unique_id TEXT NOT NULL,
+    path TEXT NOT NULL,
+    name TEXT NOT NULL,
+    vendor TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    type TEXT NOT NULL,
+    categories TEXT NOT NULL,
+    PRIMARY KEY (unique_id, path)
+);
+";
+
+/// an installed plugin, as the catalogue has it.
+#[pyclass(get_all, from_py_object)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PluginEntry {
+    /// the ID the plugin gives itself, two versions of one plugin can share it
+    pub unique_id: String,
+    pub name: String,
+    pub vendor: String,
+    pub version: u32,
+    pub path: PathBuf,
+    /// "Instrument", "Effect" and so on
+    pub kind: String,
+    /// e.g. "Instrument" and "Synth"
+    pub categories: Vec<String>,
+}
+
+impl PluginEntry {
+    /// what the scanner needs to load it.
+    pub fn info(&self) -> PluginInfo {
+        PluginInfo {
+            name: self.name.clone(),
+            manufacturer: self.vendor.clone(),
+            version: self.version,
+            plugin_type: plugin_type(&self.kind),
+            path: self.path.clone(),
+            unique_id: self.unique_id.clone(),
+        }
+    }
+}
+
+#[pymethods]
+impl PluginEntry {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "PluginEntry(name={:?}, vendor={:?}, unique_id={:?}, path={:?})",
+            self.name, self.vendor, self.unique_id, self.path
+        )
+    }
+}
+
+/// why a plugin couldn't be loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PluginError {
+    /// nothing in the catalogue has this unique ID, path or name
+    Missing(String),
+    /// more than one plugin goes by this unique ID or name
+    Ambiguous(String, Vec<PluginEntry>),
+    /// the plugin at this path was found but failed to load, with why
+    Load(PathBuf, String),
+}
+
+impl Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(plugin) => write!(
+                f,
+                "no plugin has the unique ID, path or name {plugin:?}, the plugin catalogue may need a rescan"
+            ),
+            Self::Ambiguous(plugin, entries) => {
+                let paths: Vec<_> = entries
+                    .iter()
+                    .map(|entry| entry.path.display().to_string())
+                    .collect();
+
+                write!(
+                    f,
+                    "{plugin:?} could be any of {} plugins, pick one by its path: {}",
+                    entries.len(),
+                    paths.join(", ")
+                )
+            }
+            Self::Load(path, e) => {
+                write!(f, "the plugin at {} failed to load. {e}", path.display())
+            }
+        }
+    }
+}
+
+impl Error for PluginError {}
+
+impl From<PluginError> for PyErr {
+    fn from(e: PluginError) -> Self {
+        match e {
+            PluginError::Load(..) => PyRuntimeError::new_err(e.to_string()),
+            _ => PyLookupError::new_err(e.to_string()),
+        }
+    }
+}
+
+fn plugin_type(kind: &str) -> PluginType {
+    match kind {
+        "Effect" => PluginType::Effect,
+        "Instrument" => PluginType::Instrument,
+        "Mixer" => PluginType::Mixer,
+        "FormatConverter" => PluginType::FormatConverter,
+        "Analyzer" => PluginType::Analyzer,
+        "Spatial" => PluginType::Spatial,
+        _ => PluginType::Other,
+    }
+}
+
+/// the catalogue of installed plugins. it's only as fresh as the last rescan.
+pub struct PluginCatalog {
+    db: Mutex<Connection>,
+}
+
+impl PluginCatalog {
+    /// opens (or creates) the catalogue at path.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(dir) = path.parent()
+            && let Err(e) = create_dir_all(dir)
+        {
+            warn!("couldn't make the directory for the plugin catalogue. {e}");
+        }
+
+        Self::new(Connection::open(path)?)
+    }
+
+    /// a catalogue that's forgotten when the app closes.
+    pub fn in_memory() -> Self {
+        Self::new(Connection::open_in_memory().expect("sqlite couldn't open an in memory database"))
+            .expect("sqlite couldn't make the plugin table")
+    }
+
+    /// opens the catalogue at path, or the default location, falling back to one in memory if
+    /// that fails.
+    pub fn open_or_in_memory(path: Option<PathBuf>) -> Self {
+        let path = path.unwrap_or_else(Self::default_path);
+
+        Self::open(&path).unwrap_or_else(|e| {
+            error!(
+                "couldn't open the plugin catalogue at {}. {e}",
+                path.display()
+            );
+            Self::in_memory()
+        })
+    }
+
+    /// $XDG_DATA_HOME/dream-of-daw/plugins.db, or under ~/.local/share without it.
+    pub fn default_path() -> PathBuf {
+        let data_dir = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+            .unwrap_or_default();
+
+        data_dir.join("dream-of-daw").join("plugins.db")
+    }
+
+    fn new(db: Connection) -> rusqlite::Result<Self> {
+        db.execute_batch(SCHEMA)?;
+
+        Ok(Self { db: Mutex::new(db) })
+    }
+
+    /// scans for installed plugins and replaces the catalogue with what it finds. every plugin is
+    /// loaded to read its categories, so this is slow. returns how many were found.
+    pub fn rescan(&self) -> Option<usize> {
+        let scanner = Scanner::new()
+            .inspect_err(|e| error!("couldn't make a plugin scanner. {e}"))
+            .ok()?;
+        let plugins = scanner
+            .scan()
+            .inspect_err(|e| error!("scanning for plugins failed. {e}"))
+            .ok()?;
+        let entries: Vec<PluginEntry> = plugins
+            .into_iter()
+            .map(|info| {
+                let categories = scanner
+                    .load(&info)
+                    .map(|plugin| plugin.get_categories())
+                    .unwrap_or_else(|e| {
+                        warn!("couldn't load {} to read its categories. {e}", info.name);
+                        Vec::new()
+                    });
+
+                PluginEntry {
+                    kind: format!("{:?}", info.plugin_type),
+                    unique_id: info.unique_id,
+                    name: info.name,
+                    vendor: info.manufacturer,
+                    version: info.version,
+                    path: info.path,
+                    categories,
+                }
+            })
+            .collect();
+
+        if let Err(e) = self.replace(&entries) {
+            error!("couldn't save the plugin catalogue. {e}");
+            return None;
+        }
+
+        info!("found {} plugins", entries.len());
+
+        Some(entries.len())
+    }
+
+    fn replace(&self, entries: &[PluginEntry]) -> rusqlite::Result<()> {
+        // a panic while it was locked can't have left the database half written, sqlite sees to that
+        let mut db = self.db.lock().unwrap_or_else(PoisonError::into_inner);
+        let tx = db.transaction()?;
+        tx.execute("DELETE FROM plugins", [])?;
+
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO plugins
+                    (unique_id, path, name, vendor, version, type, categories)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+
+            for entry in entries {
+                insert.execute(params![
+                    entry.unique_id,
+                    entry.path.to_string_lossy(),
+                    entry.name,
+                    entry.vendor,
+                    entry.version,
+                    entry.kind,
+                    entry.categories.join(CATEGORY_SEPARATOR),
+                ])?;
+            }
+        }
+
+        tx.commit()
+    }
+
+    /// every plugin in the catalogue, by name.
+    pub fn plugins(&self) -> Vec<PluginEntry> {
+        self.query("ORDER BY name, version DESC", [])
+    }
+
+    /// the plugin that plugin identifies: its unique ID, its path, or its name as long as no other
+    /// plugin has it. an ID shared by two installs of a plugin is as ambiguous as a shared name,
+    /// but every path is unique.
+    pub fn find(&self, plugin: &str) -> Result<PluginEntry, PluginError> {
+        for column in ["unique_id", "path", "name"] {
+            let mut entries = self.query(
+                &format!("WHERE {column} = ?1 ORDER BY version DESC"),
+                [plugin],
+            );
+
+            match entries.len() {
+                0 => continue,
+                1 => return Ok(entries.remove(0)),
+                _ => return Err(PluginError::Ambiguous(plugin.into(), entries)),
+            }
+        }
+
+        Err(PluginError::Missing(plugin.into()))
+    }
+
+    fn query(&self, filter: &str, params: impl rusqlite::Params) -> Vec<PluginEntry> {
+        let db = self.db.lock().unwrap_or_else(PoisonError::into_inner);
+        let sql = format!(
+            "SELECT unique_id, path, name, vendor, version, type, categories FROM plugins {filter}"
+        );
+        let entries = db.prepare(&sql).and_then(|mut query| {
+            query
+                .query_map(params, |row| {
+                    let categories: String = row.get(6)?;
+
+                    Ok(PluginEntry {
+                        unique_id: row.get(0)?,
+                        path: PathBuf::from(row.get::<_, String>(1)?),
+                        name: row.get(2)?,
+                        vendor: row.get(3)?,
+                        version: row.get(4)?,
+                        kind: row.get(5)?,
+                        categories: categories
+                            .split(CATEGORY_SEPARATOR)
+                            .filter(|category| !category.is_empty())
+                            .map(String::from)
+                            .collect(),
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        });
+
+        entries.unwrap_or_else(|e| {
+            error!("reading the plugin catalogue failed. {e}");
+            Vec::new()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PluginCatalog, PluginEntry, PluginError};
+    use std::path::PathBuf;
+
+    fn entry(name: &str, unique_id: &str, version: u32) -> PluginEntry {
+        PluginEntry {
+            unique_id: unique_id.into(),
+            name: name.into(),
+            vendor: "Vendor".into(),
+            version,
+            path: PathBuf::from(format!("/plugins/{name}-{version}.vst3")),
+            kind: "Instrument".into(),
+            categories: vec!["Instrument".into(), "Synth".into()],
+        }
+    }
+
+    #[test]
+    fn catalogue_replaces_and_finds_plugins() {
+        let catalog = PluginCatalog::in_memory();
+        catalog
+            .replace(&[entry("Synth", "a", 1), entry("Synth", "a", 2)])
+            .unwrap();
+        catalog
+            .replace(&[entry("Synth", "a", 2), entry("Reverb", "b", 1)])
+            .unwrap();
+
+        let plugins = catalog.plugins();
+        assert_eq!(
+            plugins,
+            vec![entry("Reverb", "b", 1), entry("Synth", "a", 2)]
+        );
+        assert_eq!(catalog.find("Synth"), Ok(entry("Synth", "a", 2)));
+        assert!(catalog.find("Piano").is_err());
+    }
+
+    #[test]
+    fn plugins_are_found_by_id_path_or_unique_name() {
+        let catalog = PluginCatalog::in_memory();
+        let old = entry("Synth", "a", 1);
+        let new = entry("Synth", "a", 2);
+        let reverb = entry("Reverb", "b", 1);
+        catalog
+            .replace(&[old.clone(), new.clone(), reverb.clone()])
+            .unwrap();
+
+        assert_eq!(catalog.find("b"), Ok(reverb.clone()));
+        assert_eq!(catalog.find("Reverb"), Ok(reverb));
+        assert_eq!(catalog.find(&old.path.to_string_lossy()), Ok(old.clone()));
+
+        // two versions share a name and an ID, only their paths tell them apart
+        let ambiguous = Err(PluginError::Ambiguous(
+            "Synth".into(),
+            vec![new.clone(), old.clone()],
+        ));
+        assert_eq!(catalog.find("Synth"), ambiguous);
+        assert!(matches!(catalog.find("a"), Err(PluginError::Ambiguous(..))));
+        assert_eq!(
+            catalog.find("Piano"),
+            Err(PluginError::Missing("Piano".into()))
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use crate::{BUFFER_FRAMES, SAMPLE_RATE};
+use pyo3::prelude::*;
+
+/// audio settings chosen when the engine starts. different handhelds need different buffer sizes
+/// to avoid underruns.
+#[pyclass(get_all, set_all, from_py_object)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EngineConfig {
+    pub sample_rate: usize,
+    /// frames per buffer, this is also the largest block plugins are asked to process.
+    pub buffer_frames: usize,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: SAMPLE_RATE,
+            buffer_frames: BUFFER_FRAMES,
+        }
+    }
+}
+
+#[pymethods]
+impl EngineConfig {
+    #[new]
+    #[pyo3(signature = (sample_rate=SAMPLE_RATE, buffer_frames=BUFFER_FRAMES))]
+    pub fn new(sample_rate: usize, buffer_frames: usize) -> Self {
+        Self {
+            sample_rate,
+            buffer_frames,
+        }
+    }
+
+    /// length of one buffer in seconds.
+    pub fn buffer_secs(&self) -> f32 {
+        self.buffer_frames as f32 / self.sample_rate as f32
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "EngineConfig(sample_rate={}, buffer_frames={})",
+            self.sample_rate, self.buffer_frames
+        )
+    }
+}
@@ -19,19 +19,36 @@ pub enum UiSector {
 }
 
 #[pyclass(from_py_object)]
-#[derive(Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct Cursor {
     #[pyo3(get, set)]
     pub sector: UiSector,
     #[pyo3(get, set)]
     pub index: isize,
+    /// how many channels ChannelSelect wraps around, should follow Mixer.get_n_channels
+    #[pyo3(get, set)]
+    pub n_channels: usize,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Self {
+            sector: UiSector::default(),
+            index: 0,
+            n_channels: N_CHANNELS,
+        }
+    }
 }
 
 #[pymethods]
 impl Cursor {
     #[new]
-    pub fn new() -> Self {
-        Self::default()
+    #[pyo3(signature = (n_channels=N_CHANNELS))]
+    pub fn new(n_channels: usize) -> Self {
+        Self {
+            n_channels,
+            ..Self::default()
+        }
     }
 
     pub fn up(&mut self) {
@@ -44,7 +61,9 @@ impl Cursor {
                 }
             }
             UiSector::Sections => self.index = (self.index - 1) % (N_SECTIONS as isize),
-            UiSector::ChannelSelect => self.index = (self.index - 1) % (N_CHANNELS as isize),
+            UiSector::ChannelSelect => {
+                self.index = (self.index - 1) % (self.n_channels.max(1) as isize)
+            }
             UiSector::Controls => {
                 if self.index < 4 {
                     self.index += 4;
@@ -78,7 +97,9 @@ impl Cursor {
                 self.index %= N_STEPS as isize;
             }
             UiSector::Sections => self.index = (self.index + 1) % (N_SECTIONS as isize),
-            UiSector::ChannelSelect => self.index = (self.index + 1) % (N_CHANNELS as isize),
+            UiSector::ChannelSelect => {
+                self.index = (self.index + 1) % (self.n_channels.max(1) as isize)
+            }
             UiSector::Controls => {
                 if self.index < 4 {
                     self.index += 4;
@@ -0,0 +1,249 @@
+//! small building blocks shared by the native instruments.
+
+use crate::Sample;
+use std::f32::consts::PI;
+
+/// how many semitones the pitch wheel bends at full throw.
+pub const PITCH_BEND_RANGE: f32 = 2.0;
+
+/// the frequency of a midi note, A4 (69) is 440 Hz.
+pub fn midi_to_hz(note: f32) -> f32 {
+    440.0 * 2.0f32.powf((note - 69.0) / 12.0)
+}
+
+/// a midi pitch bend value as semitones.
+pub fn bend_semitones(value: u16) -> f32 {
+    (value as f32 - 8192.0) / 8192.0 * PITCH_BEND_RANGE
+}
+
+/// the per-sample smoothing factor that gets a one-pole about two thirds of the way to its target
+/// in secs.
+pub fn one_pole_coef(secs: f32, sample_rate: f32) -> f32 {
+    1.0 - (-1.0 / (secs * sample_rate).max(1.0)).exp()
+}
+
+/// attack, decay and release times in seconds and the sustain level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Adsr {
+    /// attack, decay, sustain and release by index, the order the instruments list them in.
+    pub fn value_mut(&mut self, index: usize) -> Option<&mut f32> {
+        match index {
+            0 => Some(&mut self.attack),
+            1 => Some(&mut self.decay),
+            2 => Some(&mut self.sustain),
+            3 => Some(&mut self.release),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Stage {
+    #[default]
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// one voice's run through an Adsr. linear attack, exponential decay and release.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Envelope {
+    stage: Stage,
+    level: f32,
+}
+
+impl Envelope {
+    /// starts the attack from wherever the envelope is, so retriggering doesn't click.
+    pub fn trigger(&mut self) {
+        self.stage = Stage::Attack;
+    }
+
+    pub fn release(&mut self) {
+        if self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+        }
+    }
+
+    /// whether the envelope is still making sound.
+    pub fn is_active(&self) -> bool {
+        self.stage != Stage::Idle
+    }
+
+    pub fn next(&mut self, adsr: &Adsr, sample_rate: f32) -> f32 {
+        match self.stage {
+            Stage::Idle => self.level = 0.0,
+            Stage::Attack => {
+                self.level += 1.0 / (adsr.attack * sample_rate).max(1.0);
+
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level += (adsr.sustain - self.level) * one_pole_coef(adsr.decay, sample_rate);
+
+                if (self.level - adsr.sustain).abs() < 1e-4 {
+                    self.level = adsr.sustain;
+                    // a percussive envelope is done once it has decayed
+                    self.stage = if adsr.sustain > 0.0 {
+                        Stage::Sustain
+                    } else {
+                        Stage::Idle
+                    };
+                }
+            }
+            Stage::Sustain => self.level = adsr.sustain,
+            Stage::Release => {
+                self.level -= self.level * one_pole_coef(adsr.release, sample_rate);
+
+                if self.level < 1e-4 {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+
+        self.level
+    }
+}
+
+/// a naive sawtooth with its discontinuity smoothed by a polyBLEP, phase goes from 0 to 1.
+pub fn saw(phase: f32, phase_inc: f32) -> Sample {
+    2.0 * phase - 1.0 - poly_blep(phase, phase_inc)
+}
+
+fn poly_blep(phase: f32, phase_inc: f32) -> f32 {
+    if phase < phase_inc {
+        let t = phase / phase_inc;
+
+        t + t - t * t - 1.0
+    } else if phase > 1.0 - phase_inc {
+        let t = (phase - 1.0) / phase_inc;
+
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// a zero-delay-feedback state variable filter. cheap to retune every sample, which is what the
+/// filter envelopes need.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Svf {
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl Svf {
+    /// runs one sample through the filter and returns its (low-pass, band-pass, high-pass)
+    /// outputs. resonance goes from 0.0 to just under 1.0, where it starts to self oscillate.
+    pub fn run(
+        &mut self,
+        sample: Sample,
+        cutoff: f32,
+        resonance: f32,
+        sample_rate: f32,
+    ) -> [Sample; 3] {
+        let g = (PI * cutoff.clamp(10.0, sample_rate * 0.49) / sample_rate).tan();
+        let k = 2.0 - 2.0 * resonance.clamp(0.0, 0.98);
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        let v3 = sample - self.ic2eq;
+        let v1 = a1 * self.ic1eq + a2 * v3;
+        let v2 = self.ic2eq + a2 * self.ic1eq + a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        [v2, v1, sample - k * v1 - v2]
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// xorshift white noise, good enough for drums and never allocates.
+#[derive(Clone, Copy, Debug)]
+pub struct Noise(u32);
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self(0x1234_5678)
+    }
+}
+
+impl Noise {
+    pub fn next_sample(&mut self) -> Sample {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+/// which note each voice of a polyphonic instrument is playing. a voice is given to a new note
+/// when it's free, otherwise the one that has been playing the longest is stolen.
+#[derive(Clone, Copy, Debug)]
+pub struct VoiceAllocator<const N: usize> {
+    notes: [Option<u8>; N],
+    started: [u64; N],
+    clock: u64,
+}
+
+impl<const N: usize> Default for VoiceAllocator<N> {
+    fn default() -> Self {
+        Self {
+            notes: [None; N],
+            started: [0; N],
+            clock: 0,
+        }
+    }
+}
+
+impl<const N: usize> VoiceAllocator<N> {
+    /// the voice that should play note. a note that's already playing keeps its voice.
+    pub fn note_on(&mut self, note: u8, is_active: impl Fn(usize) -> bool) -> usize {
+        let voice = self
+            .notes
+            .iter()
+            .position(|playing| *playing == Some(note))
+            .or_else(|| (0..N).find(|voice| self.notes[*voice].is_none() && !is_active(*voice)))
+            .or_else(|| (0..N).find(|voice| self.notes[*voice].is_none()))
+            .unwrap_or_else(|| (0..N).min_by_key(|voice| self.started[*voice]).unwrap_or(0));
+
+        self.clock += 1;
+        self.notes[voice] = Some(note);
+        self.started[voice] = self.clock;
+
+        voice
+    }
+
+    /// frees the voice playing note, if there is one, and returns it so it can be released.
+    pub fn note_off(&mut self, note: u8) -> Option<usize> {
+        let voice = self
+            .notes
+            .iter()
+            .position(|playing| *playing == Some(note))?;
+        self.notes[voice] = None;
+
+        Some(voice)
+    }
+
+    /// frees every voice.
+    pub fn clear(&mut self) {
+        self.notes = [None; N];
+    }
+}
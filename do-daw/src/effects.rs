@@ -0,0 +1,185 @@
+//! effects that run natively, far cheaper than a VST3 on the handhelds.
+
+use crate::{
+    config::EngineConfig,
+    native::Native,
+    plugin_chain::EffectSettings,
+    traits::{ParamInfo, ProcessSamples, Processor},
+};
+use biquad::Coefficients;
+use pyo3::prelude::*;
+
+pub mod bitcrusher;
+pub mod compressor;
+pub mod delay;
+pub mod eq;
+pub mod filter;
+pub mod reverb;
+
+/// the built-in effects a slot can use in place of a plugin.
+#[pyclass(eq, eq_int, from_py_object)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NativeEffect {
+    /// a resonant low-pass, high-pass, band-pass or notch.
+    Filter,
+    /// low shelf, peaking mid and high shelf.
+    Eq,
+    /// a feedback delay with a darkening filter on the repeats. puts out only the echoes, so it
+    /// starts half wet on a channel or the master.
+    Delay,
+    /// a stereo freeverb. puts out only the reverb, so it starts half wet on a channel or the
+    /// master.
+    Reverb,
+    /// a feed-forward compressor. keys off the sidechain input when the slot has one.
+    Compressor,
+    /// bit depth and sample rate reduction.
+    Bitcrusher,
+}
+
+impl NativeEffect {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Filter => "Filter",
+            Self::Eq => "EQ",
+            Self::Delay => "Delay",
+            Self::Reverb => "Reverb",
+            Self::Compressor => "Compressor",
+            Self::Bitcrusher => "Bitcrusher",
+        }
+    }
+
+    /// the bypass and dry/wet a slot starts with when the effect is added to it. the delay and
+    /// reverb only put out their tails, fully wet they'd take the dry signal away.
+    pub fn settings(&self) -> EffectSettings {
+        match self {
+            Self::Delay | Self::Reverb => EffectSettings {
+                mix: 0.5,
+                ..EffectSettings::default()
+            },
+            _ => EffectSettings::default(),
+        }
+    }
+
+    /// builds the effect with its default parameters. allocates, so keep it off the audio thread.
+    pub fn build(&self, sample_rate: usize) -> Box<dyn ProcessSamples> {
+        match self {
+            Self::Filter => Box::new(filter::Filter::new(sample_rate)),
+            Self::Eq => Box::new(eq::Eq::new(sample_rate)),
+            Self::Delay => Box::new(delay::Delay::new(sample_rate)),
+            Self::Reverb => Box::new(reverb::Reverb::new(sample_rate)),
+            Self::Compressor => Box::new(compressor::Compressor::new(sample_rate)),
+            Self::Bitcrusher => Box::new(bitcrusher::Bitcrusher::new(sample_rate)),
+        }
+    }
+
+    /// builds the effect, ready to go in an effect slot.
+    pub fn processor(&self, config: EngineConfig) -> Box<dyn Processor> {
+        Box::new(Native::effect(self.name(), self.build(config.sample_rate)))
+    }
+}
+
+/// the values of an effect's parameters, kept within their ranges.
+#[derive(Clone, Copy, Debug)]
+struct Params<const N: usize> {
+    info: &'static [ParamInfo; N],
+    values: [f32; N],
+}
+
+impl<const N: usize> Params<N> {
+    fn new(info: &'static [ParamInfo; N]) -> Self {
+        Self {
+            info,
+            values: std::array::from_fn(|i| info[i].default),
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<f32> {
+        self.values.get(index).copied()
+    }
+
+    fn set(&mut self, index: usize, value: f32) -> bool {
+        let (Some(info), Some(current)) = (self.info.get(index), self.values.get_mut(index)) else {
+            return false;
+        };
+
+        *current = value.clamp(info.min, info.max);
+
+        true
+    }
+}
+
+/// biquad coefficients that pass everything through untouched.
+pub(crate) const FLAT: Coefficients<f32> = Coefficients {
+    a1: 0.0,
+    a2: 0.0,
+    b0: 1.0,
+    b1: 0.0,
+    b2: 0.0,
+};
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-6).log10()
+}
+
+#[cfg(test)]
+mod test {
+    use super::NativeEffect;
+
+    #[test]
+    fn effects_process_and_clamp_params() {
+        let effects = [
+            NativeEffect::Filter,
+            NativeEffect::Eq,
+            NativeEffect::Delay,
+            NativeEffect::Reverb,
+            NativeEffect::Compressor,
+            NativeEffect::Bitcrusher,
+        ];
+
+        for kind in effects {
+            let mut effect = kind.build(48000);
+
+            for (i, info) in effect.params().iter().enumerate() {
+                assert_eq!(effect.get_parameter(i), Some(info.default), "{kind:?}");
+                assert!(effect.set_parameter(i, info.max + 1000.0));
+                assert_eq!(
+                    effect.get_parameter(i),
+                    Some(info.max),
+                    "{kind:?} {}",
+                    info.name
+                );
+                assert!(effect.set_parameter(i, info.default));
+            }
+
+            let n = effect.parameter_count();
+            assert!(!effect.set_parameter(n, 0.0));
+            assert_eq!(effect.parameter_info(n), None);
+
+            // a loud tone for a while, then silence to let tails ring out
+            let input: Vec<f32> = (0..512).map(|i| (i as f32 * 0.05).sin()).collect();
+            let silence = vec![0.0; 512];
+            let mut left = vec![0.0; 512];
+            let mut right = vec![0.0; 512];
+            let mut heard = false;
+
+            for buffer in 0..200 {
+                let input = if buffer < 20 { &input } else { &silence };
+                effect.process(&[input, input], &mut [&mut left, &mut right], 512);
+
+                assert!(
+                    left.iter()
+                        .chain(&right)
+                        .all(|s| s.is_finite() && s.abs() < 4.0),
+                    "{kind:?} blew up"
+                );
+                heard |= left.iter().any(|s| s.abs() > 1e-3);
+            }
+
+            assert!(heard, "{kind:?} never made a sound");
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{
+    Sample,
+    effects::Params,
+    traits::{ParamInfo, ProcessSamples},
+};
+
+const BITS: usize = 0;
+const DOWNSAMPLE: usize = 1;
+
+const PARAMS: [ParamInfo; 2] = [
+    ParamInfo {
+        name: "Bits",
+        min: 1.0,
+        max: 16.0,
+        default: 8.0,
+        unit: "bits",
+    },
+    ParamInfo {
+        name: "Downsample",
+        min: 1.0,
+        max: 32.0,
+        default: 4.0,
+        unit: "x",
+    },
+];
+
+/// rounds every sample to fewer bits and holds each one for several samples, which aliases like
+/// an old sampler.
+pub struct Bitcrusher {
+    params: Params<2>,
+    held: [Sample; 2],
+    /// how many more samples the held ones are kept for
+    hold_left: usize,
+}
+
+impl Bitcrusher {
+    pub fn new(_sample_rate: usize) -> Self {
+        Self {
+            params: Params::new(&PARAMS),
+            held: [0.0; 2],
+            hold_left: 0,
+        }
+    }
+}
+
+impl ProcessSamples for Bitcrusher {
+    fn process(&mut self, inputs: &[&[Sample]], outputs: &mut [&mut [Sample]], num_frames: usize) {
+        let steps = 2.0f32.powf(self.params.values[BITS].round() - 1.0);
+        let hold = self.params.values[DOWNSAMPLE].round() as usize;
+
+        for frame in 0..num_frames {
+            if self.hold_left == 0 {
+                for (held, input) in self.held.iter_mut().zip(inputs) {
+                    *held = (input[frame] * steps).round() / steps;
+                }
+
+                self.hold_left = hold;
+            }
+
+            self.hold_left -= 1;
+            outputs[0][frame] = self.held[0];
+            outputs[1][frame] = self.held[1];
+        }
+    }
+
+    fn set_sample_rate(&mut self, _sample_rate: usize) {}
+
+    fn params(&self) -> &'static [ParamInfo] {
+        &PARAMS
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<f32> {
+        self.params.get(index)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        self.params.set(index, value)
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{
+    Sample,
+    dsp::one_pole_coef,
+    effects::{Params, db_to_gain, gain_to_db},
+    traits::{ParamInfo, ProcessSamples},
+};
+
+const THRESHOLD: usize = 0;
+const RATIO: usize = 1;
+const ATTACK: usize = 2;
+const RELEASE: usize = 3;
+const MAKEUP: usize = 4;
+
+const PARAMS: [ParamInfo; 5] = [
+    ParamInfo {
+        name: "Threshold",
+        min: -60.0,
+        max: 0.0,
+        default: -18.0,
+        unit: "dB",
+    },
+    ParamInfo {
+        name: "Ratio",
+        min: 1.0,
+        max: 20.0,
+        default: 4.0,
+        unit: ":1",
+    },
+    ParamInfo {
+        name: "Attack",
+        min: 0.1,
+        max: 100.0,
+        default: 10.0,
+        unit: "ms",
+    },
+    ParamInfo {
+        name: "Release",
+        min: 10.0,
+        max: 1000.0,
+        default: 100.0,
+        unit: "ms",
+    },
+    ParamInfo {
+        name: "Makeup",
+        min: 0.0,
+        max: 24.0,
+        default: 0.0,
+        unit: "dB",
+    },
+];
+
+/// a feed-forward compressor with both sides linked. when the slot has a sidechain it's the
+/// sidechain that gets listened to, which is how ducking is done.
+pub struct Compressor {
+    params: Params<5>,
+    /// how many dB the signal is being turned down by
+    reduction: f32,
+    sample_rate: f32,
+}
+
+impl Compressor {
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            params: Params::new(&PARAMS),
+            reduction: 0.0,
+            sample_rate: sample_rate as f32,
+        }
+    }
+}
+
+impl ProcessSamples for Compressor {
+    fn process(&mut self, inputs: &[&[Sample]], outputs: &mut [&mut [Sample]], num_frames: usize) {
+        let values = &self.params.values;
+        let threshold = values[THRESHOLD];
+        let slope = 1.0 - 1.0 / values[RATIO];
+        let attack = one_pole_coef(values[ATTACK] / 1000.0, self.sample_rate);
+        let release = one_pole_coef(values[RELEASE] / 1000.0, self.sample_rate);
+        let makeup = values[MAKEUP];
+        let key = if inputs.len() >= 4 {
+            &inputs[2..4]
+        } else {
+            &inputs[..2]
+        };
+
+        for frame in 0..num_frames {
+            let level = gain_to_db(key[0][frame].abs().max(key[1][frame].abs()));
+            let target = (level - threshold).max(0.0) * slope;
+            let coef = if target > self.reduction {
+                attack
+            } else {
+                release
+            };
+            self.reduction += (target - self.reduction) * coef;
+
+            let gain = db_to_gain(makeup - self.reduction);
+            outputs[0][frame] = inputs[0][frame] * gain;
+            outputs[1][frame] = inputs[1][frame] * gain;
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        self.sample_rate = sample_rate as f32;
+        self.reduction = 0.0;
+    }
+
+    fn params(&self) -> &'static [ParamInfo] {
+        &PARAMS
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<f32> {
+        self.params.get(index)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        self.params.set(index, value)
+    }
+}
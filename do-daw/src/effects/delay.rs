@@ -0,0 +1,120 @@
+use crate::{
+    Sample,
+    dsp::one_pole_coef,
+    effects::Params,
+    traits::{ParamInfo, ProcessSamples},
+};
+use std::f32::consts::TAU;
+
+const TIME: usize = 0;
+const FEEDBACK: usize = 1;
+const TONE: usize = 2;
+
+const PARAMS: [ParamInfo; 3] = [
+    ParamInfo {
+        name: "Time",
+        min: 0.01,
+        max: MAX_SECS,
+        default: 0.375,
+        unit: "s",
+    },
+    ParamInfo {
+        name: "Feedback",
+        min: 0.0,
+        max: 0.95,
+        default: 0.4,
+        unit: "",
+    },
+    ParamInfo {
+        name: "Tone",
+        min: 500.0,
+        max: 20_000.0,
+        default: 6000.0,
+        unit: "Hz",
+    },
+];
+
+/// the longest the delay can be set to.
+const MAX_SECS: f32 = 2.0;
+
+/// a stereo feedback delay. every repeat goes through a one-pole low-pass set by tone, so they
+/// darken as they die away. puts out only the echoes, it's meant for a send or a slot's wet mix.
+pub struct Delay {
+    params: Params<3>,
+    buffers: [Vec<Sample>; 2],
+    write_i: usize,
+    /// the delay time in samples, glides towards the time parameter so changing it doesn't click
+    delay: f32,
+    tone_states: [f32; 2],
+    sample_rate: f32,
+}
+
+impl Delay {
+    pub fn new(sample_rate: usize) -> Self {
+        let mut delay = Self {
+            params: Params::new(&PARAMS),
+            buffers: Default::default(),
+            write_i: 0,
+            delay: 0.0,
+            tone_states: [0.0; 2],
+            sample_rate: 0.0,
+        };
+        delay.set_sample_rate(sample_rate);
+
+        delay
+    }
+}
+
+impl ProcessSamples for Delay {
+    fn process(&mut self, inputs: &[&[Sample]], outputs: &mut [&mut [Sample]], num_frames: usize) {
+        let len = self.buffers[0].len();
+        let target = self.params.values[TIME] * self.sample_rate;
+        let glide = one_pole_coef(0.05, self.sample_rate);
+        let feedback = self.params.values[FEEDBACK];
+        let tone = 1.0 - (-TAU * self.params.values[TONE] / self.sample_rate).exp();
+
+        for frame in 0..num_frames {
+            self.delay += (target - self.delay) * glide;
+            // reads between two samples, so the glide is smooth
+            let read = (self.write_i + len) as f32 - self.delay;
+            let read_i = read.floor() as usize;
+            let frac = read.fract();
+
+            for side in 0..2 {
+                let buffer = &mut self.buffers[side];
+                let a = buffer[read_i % len];
+                let b = buffer[(read_i + 1) % len];
+                let echo = a + (b - a) * frac;
+
+                let state = &mut self.tone_states[side];
+                *state += (echo - *state) * tone;
+
+                buffer[self.write_i] = inputs[side][frame] + *state * feedback;
+                outputs[side][frame] = echo;
+            }
+
+            self.write_i = (self.write_i + 1) % len;
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        let len = (MAX_SECS * sample_rate as f32) as usize + 2;
+        self.buffers = [vec![0.0; len], vec![0.0; len]];
+        self.write_i = 0;
+        self.tone_states = [0.0; 2];
+        self.sample_rate = sample_rate as f32;
+        self.delay = self.params.values[TIME] * self.sample_rate;
+    }
+
+    fn params(&self) -> &'static [ParamInfo] {
+        &PARAMS
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<f32> {
+        self.params.get(index)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        self.params.set(index, value)
+    }
+}
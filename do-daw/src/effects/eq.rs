@@ -0,0 +1,153 @@
+use crate::{
+    Sample,
+    effects::{FLAT, Params},
+    traits::{ParamInfo, ProcessSamples},
+};
+use biquad::*;
+
+const LOW_GAIN: usize = 0;
+const LOW_FREQ: usize = 1;
+const MID_GAIN: usize = 2;
+const MID_FREQ: usize = 3;
+const MID_Q: usize = 4;
+const HIGH_GAIN: usize = 5;
+const HIGH_FREQ: usize = 6;
+
+const fn gain(name: &'static str) -> ParamInfo {
+    ParamInfo {
+        name,
+        min: -18.0,
+        max: 18.0,
+        default: 0.0,
+        unit: "dB",
+    }
+}
+
+const fn freq(name: &'static str, min: f32, max: f32, default: f32) -> ParamInfo {
+    ParamInfo {
+        name,
+        min,
+        max,
+        default,
+        unit: "Hz",
+    }
+}
+
+const PARAMS: [ParamInfo; 7] = [
+    gain("Low Gain"),
+    freq("Low Freq", 20.0, 1000.0, 120.0),
+    gain("Mid Gain"),
+    freq("Mid Freq", 100.0, 10_000.0, 1000.0),
+    ParamInfo {
+        name: "Mid Q",
+        min: 0.1,
+        max: 10.0,
+        default: Q_BUTTERWORTH_F32,
+        unit: "",
+    },
+    gain("High Gain"),
+    freq("High Freq", 1000.0, 20_000.0, 8000.0),
+];
+
+/// a three band eq, a low shelf, a peaking mid and a high shelf.
+pub struct Eq {
+    params: Params<7>,
+    /// low, mid and high for each side
+    bands: [[DirectForm2Transposed<f32>; 3]; 2],
+    sample_rate: usize,
+}
+
+impl Eq {
+    pub fn new(sample_rate: usize) -> Self {
+        let flat = DirectForm2Transposed::<f32>::new(FLAT);
+        let mut eq = Self {
+            params: Params::new(&PARAMS),
+            bands: [[flat; 3]; 2],
+            sample_rate,
+        };
+        eq.update_coefficients();
+
+        eq
+    }
+
+    fn update_coefficients(&mut self) {
+        let values = &self.params.values;
+        // biquad refuses anything at or above nyquist
+        let nyquist = self.sample_rate as f32 * 0.45;
+        let bands = [
+            (
+                Type::LowShelf(values[LOW_GAIN]),
+                values[LOW_FREQ],
+                Q_BUTTERWORTH_F32,
+            ),
+            (
+                Type::PeakingEQ(values[MID_GAIN]),
+                values[MID_FREQ],
+                values[MID_Q],
+            ),
+            (
+                Type::HighShelf(values[HIGH_GAIN]),
+                values[HIGH_FREQ],
+                Q_BUTTERWORTH_F32,
+            ),
+        ];
+
+        for (band_i, (filter_type, freq, q)) in bands.into_iter().enumerate() {
+            // on the off chance the coefficients can't be made the band keeps its old ones
+            let Ok(coeffs) = Coefficients::<f32>::from_params(
+                filter_type,
+                self.sample_rate.hz(),
+                freq.min(nyquist).hz(),
+                q,
+            ) else {
+                continue;
+            };
+
+            self.bands
+                .iter_mut()
+                .for_each(|side| side[band_i].update_coefficients(coeffs));
+        }
+    }
+}
+
+impl ProcessSamples for Eq {
+    fn process(&mut self, inputs: &[&[Sample]], outputs: &mut [&mut [Sample]], num_frames: usize) {
+        for ((input, output), bands) in inputs.iter().zip(outputs.iter_mut()).zip(&mut self.bands) {
+            for (input, output) in input[..num_frames]
+                .iter()
+                .zip(output[..num_frames].iter_mut())
+            {
+                *output = bands
+                    .iter_mut()
+                    .fold(*input, |sample, band| band.run(sample));
+            }
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        self.sample_rate = sample_rate;
+        self.bands
+            .iter_mut()
+            .flatten()
+            .for_each(|band| band.reset_state());
+        self.update_coefficients();
+    }
+
+    fn params(&self) -> &'static [ParamInfo] {
+        &PARAMS
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<f32> {
+        self.params.get(index)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        let set = self.params.set(index, value);
+
+        if set {
+            self.update_coefficients();
+        }
+
+        set
+    }
+}
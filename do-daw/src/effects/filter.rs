@@ -0,0 +1,119 @@
+use crate::{
+    Sample,
+    effects::{FLAT, Params},
+    traits::{ParamInfo, ProcessSamples},
+};
+use biquad::*;
+
+const MODE: usize = 0;
+const CUTOFF: usize = 1;
+const Q: usize = 2;
+
+const PARAMS: [ParamInfo; 3] = [
+    ParamInfo {
+        name: "Mode",
+        min: 0.0,
+        max: 3.0,
+        default: 0.0,
+        unit: "",
+    },
+    ParamInfo {
+        name: "Cutoff",
+        min: 20.0,
+        max: 20_000.0,
+        default: 1000.0,
+        unit: "Hz",
+    },
+    ParamInfo {
+        name: "Q",
+        min: 0.1,
+        max: 10.0,
+        default: Q_BUTTERWORTH_F32,
+        unit: "",
+    },
+];
+
+/// a resonant biquad. the mode parameter picks low-pass (0), high-pass (1), band-pass (2) or
+/// notch (3).
+pub struct Filter {
+    params: Params<3>,
+    filters: [DirectForm2Transposed<f32>; 2],
+    sample_rate: usize,
+}
+
+impl Filter {
+    pub fn new(sample_rate: usize) -> Self {
+        let mut filter = Self {
+            params: Params::new(&PARAMS),
+            filters: [DirectForm2Transposed::<f32>::new(FLAT); 2],
+            sample_rate,
+        };
+        filter.update_coefficients();
+
+        filter
+    }
+
+    fn update_coefficients(&mut self) {
+        let filter_type = match self.params.values[MODE].round() as u8 {
+            0 => Type::LowPass,
+            1 => Type::HighPass,
+            2 => Type::BandPass,
+            _ => Type::Notch,
+        };
+        // biquad refuses anything at or above nyquist
+        let cutoff = self.params.values[CUTOFF].min(self.sample_rate as f32 * 0.45);
+
+        // on the off chance the coefficients can't be made the filter keeps its old ones
+        if let Ok(coeffs) = Coefficients::<f32>::from_params(
+            filter_type,
+            self.sample_rate.hz(),
+            cutoff.hz(),
+            self.params.values[Q],
+        ) {
+            self.filters
+                .iter_mut()
+                .for_each(|filter| filter.update_coefficients(coeffs));
+        }
+    }
+}
+
+impl ProcessSamples for Filter {
+    fn process(&mut self, inputs: &[&[Sample]], outputs: &mut [&mut [Sample]], num_frames: usize) {
+        for ((input, output), filter) in
+            inputs.iter().zip(outputs.iter_mut()).zip(&mut self.filters)
+        {
+            for (input, output) in input[..num_frames]
+                .iter()
+                .zip(output[..num_frames].iter_mut())
+            {
+                *output = filter.run(*input);
+            }
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        self.sample_rate = sample_rate;
+        self.filters
+            .iter_mut()
+            .for_each(|filter| filter.reset_state());
+        self.update_coefficients();
+    }
+
+    fn params(&self) -> &'static [ParamInfo] {
+        &PARAMS
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<f32> {
+        self.params.get(index)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        let set = self.params.set(index, value);
+
+        if set {
+            self.update_coefficients();
+        }
+
+        set
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    Sample,
+    effects::Params,
+    traits::{ParamInfo, ProcessSamples},
+};
+
+const ROOM_SIZE: usize = 0;
+const DAMPING: usize = 1;
+const WIDTH: usize = 2;
+
+const PARAMS: [ParamInfo; 3] = [
+    ParamInfo {
+        name: "Room Size",
+        min: 0.0,
+        max: 1.0,
+        default: 0.5,
+        unit: "",
+    },
+    ParamInfo {
+        name: "Damping",
+        min: 0.0,
+        max: 1.0,
+        default: 0.5,
+        unit: "",
+    },
+    ParamInfo {
+        name: "Width",
+        min: 0.0,
+        max: 1.0,
+        default: 1.0,
+        unit: "",
+    },
+];
+
+/// freeverb's delay lengths in samples at 44.1kHz.
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+/// how much longer the right side's delays are than the left's, to decorrelate them.
+const STEREO_SPREAD: usize = 23;
+/// keeps the sum of eight combs from getting too loud.
+const INPUT_GAIN: f32 = 0.015;
+
+/// a low-passed feedback comb filter.
+#[derive(Debug, Default)]
+struct Comb {
+    buffer: Vec<Sample>,
+    i: usize,
+    filter_state: f32,
+}
+
+impl Comb {
+    fn run(&mut self, input: Sample, feedback: f32, damping: f32) -> Sample {
+        let output = self.buffer[self.i];
+        self.filter_state = output * (1.0 - damping) + self.filter_state * damping;
+        self.buffer[self.i] = input + self.filter_state * feedback;
+        self.i = (self.i + 1) % self.buffer.len();
+
+        output
+    }
+}
+
+/// freeverb's approximation of an all-pass.
+#[derive(Debug, Default)]
+struct Allpass {
+    buffer: Vec<Sample>,
+    i: usize,
+}
+
+impl Allpass {
+    fn run(&mut self, input: Sample) -> Sample {
+        let delayed = self.buffer[self.i];
+        self.buffer[self.i] = input + delayed * 0.5;
+        self.i = (self.i + 1) % self.buffer.len();
+
+        delayed - input
+    }
+}
+
+/// the delay lines for one side.
+#[derive(Debug, Default)]
+struct Tank {
+    combs: [Comb; 8],
+    allpasses: [Allpass; 4],
+}
+
+impl Tank {
+    fn new(sample_rate: usize, spread: usize) -> Self {
+        let scale = |tuning: usize| ((tuning + spread) * sample_rate / 44_100).max(1);
+        let mut tank = Self::default();
+
+        for (comb, tuning) in tank.combs.iter_mut().zip(COMB_TUNINGS) {
+            comb.buffer = vec![0.0; scale(tuning)];
+        }
+
+        for (allpass, tuning) in tank.allpasses.iter_mut().zip(ALLPASS_TUNINGS) {
+            allpass.buffer = vec![0.0; scale(tuning)];
+        }
+
+        tank
+    }
+
+    fn run(&mut self, input: Sample, feedback: f32, damping: f32) -> Sample {
+        let combed = self
+            .combs
+            .iter_mut()
+            .map(|comb| comb.run(input, feedback, damping))
+            .sum();
+
+        self.allpasses
+            .iter_mut()
+            .fold(combed, |sample, allpass| allpass.run(sample))
+    }
+}
+
+/// a stereo freeverb, eight parallel combs into four all-passes for each side. puts out only the
+/// reverb, it's meant for a send or a slot's wet mix.
+pub struct Reverb {
+    params: Params<3>,
+    tanks: [Tank; 2],
+}
+
+impl Reverb {
+    pub fn new(sample_rate: usize) -> Self {
+        let mut reverb = Self {
+            params: Params::new(&PARAMS),
+            tanks: Default::default(),
+        };
+        reverb.set_sample_rate(sample_rate);
+
+        reverb
+    }
+}
+
+impl ProcessSamples for Reverb {
+    fn process(&mut self, inputs: &[&[Sample]], outputs: &mut [&mut [Sample]], num_frames: usize) {
+        let values = &self.params.values;
+        let feedback = values[ROOM_SIZE] * 0.28 + 0.7;
+        let damping = values[DAMPING] * 0.4;
+        let wet_same = 0.5 + values[WIDTH] * 0.5;
+        let wet_other = (1.0 - values[WIDTH]) * 0.5;
+
+        for frame in 0..num_frames {
+            let input = (inputs[0][frame] + inputs[1][frame]) * INPUT_GAIN;
+            let left = self.tanks[0].run(input, feedback, damping);
+            let right = self.tanks[1].run(input, feedback, damping);
+
+            outputs[0][frame] = left * wet_same + right * wet_other;
+            outputs[1][frame] = right * wet_same + left * wet_other;
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        self.tanks = [
+            Tank::new(sample_rate, 0),
+            Tank::new(sample_rate, STEREO_SPREAD),
+        ];
+    }
+
+    fn params(&self) -> &'static [ParamInfo] {
+        &PARAMS
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<f32> {
+        self.params.get(index)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        self.params.set(index, value)
+    }
+}
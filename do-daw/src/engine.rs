@@ -0,0 +1,1244 @@
+//! the audio thread's side of the mixer. the engine owns every plugin chain and the master
+//! effects outright, everything else changes them by sending a MixerCommand down a bounded,
+//! lock-free queue. plugins the engine lets go of are handed back through a second queue so they
+//! are dropped off the audio thread.
+
+use crate::{
+    MAX_CHANNELS, N_AUX, N_EFFECTS, Sample, StereoBuffer,
+    config::EngineConfig,
+    master::{MasterSettings, MasterStage},
+    meter::{Meter, Meters},
+    plugin_chain::{
+        ChannelSettings, EffectSettings, EffectSlot, PanLaw, PluginChain, Sidechain, all_notes_off,
+        move_slot, new_stereo_buffer, run_effects,
+    },
+    quarantine::PluginHealth,
+    traits::Processor,
+};
+use crossbeam::channel::{Receiver, Sender, TrySendError};
+use log::*;
+use rack::prelude::*;
+use rayon::prelude::*;
+use std::{
+    fmt::{self, Display},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Instant,
+};
+
+/// how many commands (and how much garbage) can be waiting on the audio thread at once.
+pub const QUEUE_LEN: usize = 1024;
+
+/// one of the effect chains of the mixer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EffectChain {
+    Channel(usize),
+    Aux(usize),
+    Master,
+}
+
+impl From<Option<usize>> for EffectChain {
+    /// `None` is the master effects. the Python effect methods take their channel this way.
+    fn from(channel: Option<usize>) -> Self {
+        channel.map_or(Self::Master, Self::Channel)
+    }
+}
+
+/// an instrument or effect slot, like the one a parameter belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ParamTarget {
+    /// a channel's instrument
+    Instrument(usize),
+    /// an effect slot of a chain
+    Effect(EffectChain, usize),
+}
+
+/// a plugin lent out of its slot, so its state can be read or restored off the audio thread.
+pub struct Loan {
+    pub target: ParamTarget,
+    /// a placeholder on the way to the engine, the slot's plugin once it's lent
+    pub plugin: Box<dyn Processor>,
+    /// whether plugin came out of the slot, false if the slot is empty or doesn't exist
+    pub lent: bool,
+}
+
+impl Loan {
+    /// asks for the plugin in target. the placeholder that holds the slot meanwhile is made here,
+    /// so lending doesn't allocate.
+    pub fn new(target: ParamTarget) -> Self {
+        Self {
+            target,
+            plugin: Box::new(Placeholder),
+            lent: false,
+        }
+    }
+}
+
+/// holds a slot while its plugin is lent out. as an instrument it's silent, as an effect it
+/// passes its input through.
+struct Placeholder;
+
+impl Processor for Placeholder {
+    fn name(&self) -> &str {
+        "Placeholder"
+    }
+
+    fn initialize(&mut self, _config: EngineConfig) {}
+
+    fn process(
+        &mut self,
+        inputs: &[&[Sample]],
+        outputs: &mut [&mut [Sample]],
+        num_frames: usize,
+    ) -> bool {
+        for (i, output) in outputs.iter_mut().enumerate() {
+            match inputs.get(i) {
+                Some(input) => output[..num_frames].copy_from_slice(&input[..num_frames]),
+                None => output[..num_frames].fill(0.0),
+            }
+        }
+
+        true
+    }
+}
+
+impl Display for Placeholder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "placeholder for a lent plugin")
+    }
+}
+
+/// a change to the engine's state.
+pub enum MixerCommand {
+    SetInstrument {
+        channel_i: usize,
+        sound_gen: Box<dyn Processor>,
+        health: Arc<PluginHealth>,
+    },
+    AddEffect {
+        chain: EffectChain,
+        location: usize,
+        plugin: Box<dyn Processor>,
+        health: Arc<PluginHealth>,
+        settings: EffectSettings,
+    },
+    RmEffect {
+        chain: EffectChain,
+        effect: usize,
+    },
+    MoveEffect {
+        chain: EffectChain,
+        from: usize,
+        to: usize,
+    },
+    SwapEffects {
+        chain: EffectChain,
+        a: usize,
+        b: usize,
+    },
+    ReplaceEffect {
+        chain: EffectChain,
+        effect: usize,
+        plugin: Box<dyn Processor>,
+        health: Arc<PluginHealth>,
+    },
+    SetEffectSettings {
+        chain: EffectChain,
+        effect: usize,
+        settings: EffectSettings,
+    },
+    SetChannelSettings {
+        channel_i: usize,
+        settings: ChannelSettings,
+    },
+    SetParameter {
+        target: ParamTarget,
+        index: usize,
+        value: f32,
+    },
+    SetPanLaw(PanLaw),
+    SetMasterSettings(MasterSettings),
+    /// plays event on a channel's instrument at frame of the engine's clock
+    SendMidi {
+        channel_i: usize,
+        event: MidiEvent,
+        frame: u64,
+    },
+    /// appends a channel, built (and boxed) off the audio thread so adding it doesn't allocate.
+    /// the engine resizes it if it was built for a different buffer size
+    AddChannel(Box<PluginChain>),
+    /// removes a channel, the ones after it move down by one
+    RemoveChannel(usize),
+    /// swaps the placeholder of each loan into its slot and sends the slots' plugins back through
+    /// reply. if nobody is waiting on reply anymore they go straight back into their slots
+    LendPlugins {
+        loans: Vec<Loan>,
+        reply: Sender<Vec<Loan>>,
+    },
+    /// puts lent plugins back into their slots
+    ReturnPlugins(Vec<Loan>),
+}
+
+/// something the audio thread is done with and that should be dropped elsewhere.
+pub enum Garbage {
+    /// an instrument or effect and its health record, which the UI may have already let go of
+    Plugin(Box<dyn Processor>, Arc<PluginHealth>),
+    /// a removed channel, along with its instrument and effects
+    Channel(Box<PluginChain>),
+    /// the placeholders that held the slots of lent plugins, or a plugin whose slot went away
+    /// while it was lent
+    Loans(Vec<Loan>),
+}
+
+/// where the engine is in the frames it has put out, shared so other threads can stamp midi with
+/// the frame it should play at.
+#[derive(Debug)]
+pub struct EngineClock {
+    epoch: Instant,
+    /// the first frame of the buffer the engine is on
+    frame: AtomicU64,
+    /// when the engine started on that buffer, in nanoseconds since epoch
+    started: AtomicU64,
+    sample_rate: AtomicUsize,
+    buffer_frames: AtomicUsize,
+}
+
+impl EngineClock {
+    fn new(config: EngineConfig) -> Self {
+        Self {
+            epoch: Instant::now(),
+            frame: AtomicU64::new(0),
+            started: AtomicU64::new(0),
+            sample_rate: AtomicUsize::new(config.sample_rate),
+            buffer_frames: AtomicUsize::new(config.buffer_frames),
+        }
+    }
+
+    fn start_buffer(&self, frame: u64) {
+        self.started
+            .store(self.epoch.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        self.frame.store(frame, Ordering::Relaxed);
+    }
+
+    fn set_config(&self, config: EngineConfig) {
+        self.sample_rate
+            .store(config.sample_rate, Ordering::Relaxed);
+        self.buffer_frames
+            .store(config.buffer_frames, Ordering::Relaxed);
+    }
+
+    /// the frame playing right now, going by how long ago the engine started on its buffer. never
+    /// more than a buffer past it, so a stalled engine doesn't run the clock off.
+    pub fn now(&self) -> u64 {
+        let elapsed = (self.epoch.elapsed().as_nanos() as u64)
+            .saturating_sub(self.started.load(Ordering::Relaxed));
+        let elapsed_frames =
+            elapsed as u128 * self.sample_rate.load(Ordering::Relaxed) as u128 / 1_000_000_000;
+        let buffer_frames = self.buffer_frames.load(Ordering::Relaxed) as u64;
+
+        self.frame.load(Ordering::Relaxed) + (elapsed_frames as u64).min(buffer_frames)
+    }
+
+    /// the frame midi sent right now should play at. that's a buffer after now, which lands in a
+    /// buffer the engine hasn't started on, so events keep the spacing they were sent with instead
+    /// of bunching up at the start of the next buffer.
+    pub fn stamp(&self) -> u64 {
+        self.now() + self.buffer_frames.load(Ordering::Relaxed) as u64
+    }
+}
+
+/// a shared effect chain that channels feed through their sends. its output is mixed into the
+/// master bus next to the channels.
+pub struct AuxBus {
+    pub effects: Vec<EffectSlot>,
+    /// the summed sends and the scratch buffer the effects ping-pong with.
+    buffers: [StereoBuffer; 2],
+}
+
+impl AuxBus {
+    fn new(buffer_frames: usize) -> Self {
+        Self {
+            effects: Vec::with_capacity(N_EFFECTS),
+            buffers: [
+                new_stereo_buffer(buffer_frames),
+                new_stereo_buffer(buffer_frames),
+            ],
+        }
+    }
+}
+
+/// the summing, master processing and metering of a Mixer, along with the plugin chains it sums.
+/// the live output device and offline renders both pull their audio through this.
+pub struct MixerEngine {
+    /// boxed so channels move in and out of the engine without the audio thread allocating
+    #[allow(clippy::vec_box)]
+    channels: Vec<Box<PluginChain>>,
+    /// global effects on the output of all channels. these get applied after the channels are
+    /// mixed together.
+    effects: Vec<EffectSlot>,
+    aux: Vec<AuxBus>,
+    pan_law: PanLaw,
+    meters: Arc<Meters>,
+    commands: Receiver<MixerCommand>,
+    garbage: Sender<Garbage>,
+    /// garbage the janitor's queue had no room for. no more commands are applied until it's
+    /// handed over, so the audio thread never drops a plugin itself
+    pending_garbage: Option<Garbage>,
+    /// saturation or limiting, after the master effects
+    master: MasterStage,
+    /// the master bus and the scratch buffer the master effects ping-pong with.
+    buffers: [StereoBuffer; 2],
+    /// what sidechained effects hear when their source is silent
+    silence: Vec<Sample>,
+    config: EngineConfig,
+    /// the first frame of the next buffer, counted from when the engine was made
+    frame: u64,
+    clock: Arc<EngineClock>,
+    /// whether midi from the command queue is played. an offline render turns it off and plays
+    /// its own midi with queue_midi, so nothing sent live ends up in the file
+    live_midi: bool,
+}
+
+impl MixerEngine {
+    pub fn new(
+        n_channels: usize,
+        config: EngineConfig,
+        meters: Arc<Meters>,
+        commands: Receiver<MixerCommand>,
+        garbage: Sender<Garbage>,
+    ) -> Self {
+        Self {
+            channels: {
+                // room for every channel that could be added, so adding one never reallocates
+                let mut channels = Vec::with_capacity(MAX_CHANNELS);
+                channels.extend(
+                    (0..n_channels.min(MAX_CHANNELS))
+                        .map(|_| Box::new(PluginChain::new(config.buffer_frames))),
+                );
+                channels
+            },
+            effects: Vec::with_capacity(N_EFFECTS),
+            aux: (0..N_AUX)
+                .map(|_| AuxBus::new(config.buffer_frames))
+                .collect(),
+            pan_law: PanLaw::default(),
+            meters,
+            commands,
+            garbage,
+            pending_garbage: None,
+            master: MasterStage::new(MasterSettings::default(), config.sample_rate),
+            buffers: [
+                new_stereo_buffer(config.buffer_frames),
+                new_stereo_buffer(config.buffer_frames),
+            ],
+            silence: vec![0.0; config.buffer_frames],
+            config,
+            frame: 0,
+            clock: Arc::new(EngineClock::new(config)),
+            live_midi: true,
+        }
+    }
+
+    pub fn config(&self) -> EngineConfig {
+        self.config
+    }
+
+    /// the first frame of the next buffer the engine puts out.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn clock(&self) -> Arc<EngineClock> {
+        self.clock.clone()
+    }
+
+    /// switches the engine to a new sample rate and buffer size, re-initializing every plugin.
+    /// this allocates, so the output device should be stopped (or locked out) while it runs.
+    pub fn reconfigure(&mut self, config: EngineConfig) {
+        info!("reconfiguring the engine for {config:?}");
+        // anything that was queued for the old setup still applies
+        self.handle_commands();
+
+        self.config = config;
+        self.clock.set_config(config);
+        self.channels
+            .iter_mut()
+            .for_each(|channel| channel.reconfigure(config));
+        self.effects
+            .iter_mut()
+            .chain(self.aux.iter_mut().flat_map(|aux| aux.effects.iter_mut()))
+            .for_each(|slot| slot.plugin.initialize(config));
+        self.aux.iter_mut().for_each(|aux| {
+            aux.buffers = [
+                new_stereo_buffer(config.buffer_frames),
+                new_stereo_buffer(config.buffer_frames),
+            ]
+        });
+        self.master = MasterStage::new(self.master.settings(), config.sample_rate);
+        self.buffers = [
+            new_stereo_buffer(config.buffer_frames),
+            new_stereo_buffer(config.buffer_frames),
+        ];
+        self.silence = vec![0.0; config.buffer_frames];
+    }
+
+    /// turns midi from the command queue on or off. while it's off SendMidi commands are dropped.
+    pub fn set_live_midi(&mut self, on: bool) {
+        self.live_midi = on;
+    }
+
+    /// queues event for the instrument of a channel, to play at frame of the engine's clock,
+    /// without going through the command queue.
+    pub fn queue_midi(&mut self, channel_i: usize, frame: u64, event: MidiEvent) {
+        if let Some(channel) = self.channels.get_mut(channel_i) {
+            channel.queue_midi(frame, event);
+        }
+    }
+
+    /// applies the waiting commands, then drops the midi they queued and stops every note, so
+    /// nothing that was playing carries over into what's played next.
+    pub fn stop_all_notes(&mut self) {
+        self.handle_commands();
+        self.channels
+            .iter_mut()
+            .for_each(|channel| channel.stop_all_notes());
+    }
+
+    /// applies every command that is waiting in the queue. never blocks. if the janitor falls
+    /// behind, the rest of the commands wait for a later buffer.
+    pub fn handle_commands(&mut self) {
+        let mut handled = false;
+
+        while self.collect_garbage()
+            && let Ok(command) = self.commands.try_recv()
+        {
+            self.handle_command(command);
+            handled = true;
+        }
+
+        if handled {
+            self.update_sidechain_taps();
+        }
+    }
+
+    /// hands the pending garbage to the janitor thread. false if its queue is still full.
+    fn collect_garbage(&mut self) -> bool {
+        let Some(garbage) = self.pending_garbage.take() else {
+            return true;
+        };
+
+        match self.garbage.try_send(garbage) {
+            Ok(()) => true,
+            // there's no janitor to hand it to, so it's dropped here
+            Err(TrySendError::Disconnected(_)) => true,
+            Err(TrySendError::Full(garbage)) => {
+                self.pending_garbage = Some(garbage);
+                false
+            }
+        }
+    }
+
+    /// tells every channel whether an effect sidechains it pre-fader, so it keeps that signal.
+    fn update_sidechain_taps(&mut self) {
+        for channel_i in 0..self.channels.len() {
+            let tap = Some(Sidechain {
+                channel: channel_i,
+                pre_fader: true,
+            });
+            let tapped = self
+                .channels
+                .iter()
+                .flat_map(|channel| channel.effects.iter())
+                .chain(self.effects.iter())
+                .chain(self.aux.iter().flat_map(|aux| aux.effects.iter()))
+                .any(|slot| slot.settings.sidechain == tap);
+
+            self.channels[channel_i].pre_fader_tapped = tapped;
+        }
+    }
+
+    fn effects_mut(&mut self, chain: EffectChain) -> Option<&mut Vec<EffectSlot>> {
+        match chain {
+            EffectChain::Channel(channel_i) => self
+                .channels
+                .get_mut(channel_i)
+                .map(|chan| &mut chan.effects),
+            EffectChain::Aux(aux) => self.aux.get_mut(aux).map(|aux| &mut aux.effects),
+            EffectChain::Master => Some(&mut self.effects),
+        }
+    }
+
+    /// the instrument or effect in a slot, None if the slot is empty or doesn't exist.
+    fn processor_mut(&mut self, target: ParamTarget) -> Option<&mut Box<dyn Processor>> {
+        match target {
+            ParamTarget::Instrument(channel_i) => self
+                .channels
+                .get_mut(channel_i)
+                .and_then(|chan| chan.sound_gen.as_mut()),
+            ParamTarget::Effect(chain, effect) => self
+                .effects_mut(chain)
+                .and_then(|effects| effects.get_mut(effect))
+                .map(|slot| &mut slot.plugin),
+        }
+    }
+
+    /// swaps the placeholder of each loan into its slot, so the plugin that was there can be used
+    /// off the audio thread. an instrument stops its notes first, so none of them hang while the
+    /// midi goes to the placeholder.
+    fn lend(&mut self, loans: &mut [Loan]) {
+        for loan in loans.iter_mut() {
+            let Some(plugin) = self.processor_mut(loan.target) else {
+                continue;
+            };
+
+            std::mem::swap(plugin, &mut loan.plugin);
+            loan.lent = true;
+
+            if let ParamTarget::Instrument(_) = loan.target {
+                all_notes_off(loan.plugin.as_mut());
+            }
+        }
+    }
+
+    /// puts the lent plugins back, which leaves the placeholders in loans.
+    fn take_back(&mut self, loans: &mut [Loan]) {
+        for loan in loans.iter_mut().filter(|loan| loan.lent) {
+            if let Some(plugin) = self.processor_mut(loan.target) {
+                std::mem::swap(plugin, &mut loan.plugin);
+                loan.lent = false;
+            }
+        }
+    }
+
+    fn handle_command(&mut self, command: MixerCommand) {
+        // the plugin this command pushed out, if any. it's dropped off the audio thread
+        let unused = match command {
+            MixerCommand::SetInstrument {
+                channel_i,
+                sound_gen,
+                health,
+            } => match self.channels.get_mut(channel_i) {
+                Some(channel) => {
+                    let old_health = std::mem::replace(&mut channel.sound_gen_health, health);
+
+                    channel
+                        .sound_gen
+                        .replace(sound_gen)
+                        .map(|old| (old, old_health))
+                }
+                None => Some((sound_gen, health)),
+            },
+            MixerCommand::AddEffect {
+                chain,
+                location,
+                plugin,
+                health,
+                settings,
+            } => match self.effects_mut(chain) {
+                // the length check keeps the insert from growing (and reallocating) the vec
+                Some(effects) if effects.len() < N_EFFECTS => {
+                    let slot = EffectSlot::new(plugin, health, settings);
+
+                    if location < effects.len() {
+                        effects.insert(location, slot);
+                    } else {
+                        effects.push(slot);
+                    }
+
+                    None
+                }
+                _ => Some((plugin, health)),
+            },
+            MixerCommand::RmEffect { chain, effect } => self
+                .effects_mut(chain)
+                .filter(|effects| effect < effects.len())
+                .map(|effects| effects.remove(effect))
+                .map(|slot| (slot.plugin, slot.health)),
+            MixerCommand::MoveEffect { chain, from, to } => {
+                if let Some(effects) = self.effects_mut(chain) {
+                    move_slot(effects, from, to);
+                }
+
+                None
+            }
+            MixerCommand::SwapEffects { chain, a, b } => {
+                if let Some(effects) = self.effects_mut(chain)
+                    && a < effects.len()
+                    && b < effects.len()
+                {
+                    effects.swap(a, b);
+                }
+
+                None
+            }
+            // the slot keeps its bypass and dry/wet, only the plugin changes
+            MixerCommand::ReplaceEffect {
+                chain,
+                effect,
+                plugin,
+                health,
+            } => match self
+                .effects_mut(chain)
+                .and_then(|effects| effects.get_mut(effect))
+            {
+                Some(slot) => Some((
+                    std::mem::replace(&mut slot.plugin, plugin),
+                    std::mem::replace(&mut slot.health, health),
+                )),
+                None => Some((plugin, health)),
+            },
+            MixerCommand::SetEffectSettings {
+                chain,
+                effect,
+                settings,
+            } => {
+                if let Some(slot) = self
+                    .effects_mut(chain)
+                    .and_then(|effects| effects.get_mut(effect))
+                {
+                    slot.settings = settings;
+                }
+
+                None
+            }
+            MixerCommand::SetChannelSettings {
+                channel_i,
+                settings,
+            } => {
+                if let Some(channel) = self.channels.get_mut(channel_i) {
+                    channel.settings = settings;
+                }
+
+                None
+            }
+            MixerCommand::SetParameter {
+                target,
+                index,
+                value,
+            } => {
+                if let Some(processor) = self.processor_mut(target) {
+                    processor.set_parameter(index, value);
+                }
+
+                None
+            }
+            MixerCommand::SetPanLaw(pan_law) => {
+                self.pan_law = pan_law;
+
+                None
+            }
+            MixerCommand::SetMasterSettings(settings) => {
+                self.master.set_settings(settings);
+
+                None
+            }
+            MixerCommand::SendMidi {
+                channel_i,
+                event,
+                frame,
+            } => {
+                if self.live_midi {
+                    self.queue_midi(channel_i, frame, event);
+                }
+
+                None
+            }
+            MixerCommand::AddChannel(mut channel) => {
+                if self.channels.len() < MAX_CHANNELS {
+                    // the engine may have been reconfigured after the channel was built. resizing
+                    // allocates, but it only happens in that race
+                    if channel.buffer_frames() != self.config.buffer_frames {
+                        channel.reconfigure(self.config);
+                    }
+
+                    self.channels.push(channel);
+                } else {
+                    self.pending_garbage = Some(Garbage::Channel(channel));
+                }
+
+                None
+            }
+            MixerCommand::RemoveChannel(channel_i) => {
+                if channel_i < self.channels.len() {
+                    let channel = self.channels.remove(channel_i);
+                    self.pending_garbage = Some(Garbage::Channel(channel));
+                    self.meters.channel_removed(channel_i);
+
+                    self.channels
+                        .iter_mut()
+                        .flat_map(|channel| channel.effects.iter_mut())
+                        .chain(self.effects.iter_mut())
+                        .chain(self.aux.iter_mut().flat_map(|aux| aux.effects.iter_mut()))
+                        .for_each(|slot| slot.settings.channel_removed(channel_i));
+                }
+
+                None
+            }
+            MixerCommand::LendPlugins { mut loans, reply } => {
+                self.lend(&mut loans);
+
+                // the UI gave up waiting, so nothing was changed after all
+                if let Err(e) = reply.try_send(loans) {
+                    let mut loans = e.into_inner();
+                    self.take_back(&mut loans);
+                    self.pending_garbage = Some(Garbage::Loans(loans));
+                }
+
+                None
+            }
+            MixerCommand::ReturnPlugins(mut loans) => {
+                self.take_back(&mut loans);
+                self.pending_garbage = Some(Garbage::Loans(loans));
+
+                None
+            }
+        };
+
+        // each command lets go of one thing at most, and it's only applied once the last
+        // command's garbage is gone
+        if let Some((plugin, health)) = unused {
+            self.pending_garbage = Some(Garbage::Plugin(plugin, health));
+        }
+    }
+
+    /// applies waiting commands, then pulls n_frames (at most EngineConfig.buffer_frames) from every channel,
+    /// mixes them and runs the result through the master effects. the returned buffers are owned
+    /// by the engine and get overwritten by the next call.
+    pub fn process(&mut self, n_frames: usize) -> [&[Sample]; 2] {
+        self.handle_commands();
+
+        let buffer_start = self.frame;
+        self.frame += n_frames as u64;
+        self.clock.start_buffer(buffer_start);
+        let buffer_secs = n_frames as f32 / self.config.sample_rate as f32;
+        let pan_law = self.pan_law;
+        let any_solo = self.channels.iter().any(|channel| channel.settings.solo);
+
+        let silence = &self.silence[..n_frames];
+
+        // muted channels are still processed so their tails and LFOs stay in phase
+        self.channels
+            .par_iter_mut()
+            .zip(self.meters.channels.par_iter())
+            .filter(|(channel, _)| !channel.has_sidechain())
+            .for_each(|(channel, meter)| {
+                let output =
+                    channel.get_samples(n_frames, buffer_start, pan_law, |_| [silence, silence]);
+                update_meter(meter, output, buffer_secs);
+            });
+
+        // channels with sidechained effects go after the rest, so they hear this buffer of their
+        // sources. a source that is sidechained itself and comes later is heard a buffer late
+        for channel_i in 0..self.channels.len() {
+            if !self.channels[channel_i].has_sidechain() {
+                continue;
+            }
+
+            let (before, rest) = self.channels.split_at_mut(channel_i);
+            let Some((channel, after)) = rest.split_first_mut() else {
+                continue;
+            };
+            let (before, after) = (&*before, &*after);
+            let output = channel.get_samples(n_frames, buffer_start, pan_law, |source| {
+                let source_channel = if source.channel < channel_i {
+                    before.get(source.channel)
+                } else {
+                    // a channel can't sidechain itself, that wraps around to None
+                    after.get(source.channel.wrapping_sub(channel_i + 1))
+                };
+
+                sidechain_input(source_channel.map(Box::as_ref), source, silence, n_frames)
+            });
+            update_meter(&self.meters.channels[channel_i], output, buffer_secs);
+        }
+
+        let [mix_left, mix_right] = &mut self.buffers[0];
+        let (mix_left, mix_right) = (&mut mix_left[..n_frames], &mut mix_right[..n_frames]);
+        mix_left.fill(0.0);
+        mix_right.fill(0.0);
+
+        for aux in self.aux.iter_mut() {
+            let [left, right] = &mut aux.buffers[0];
+            left[..n_frames].fill(0.0);
+            right[..n_frames].fill(0.0);
+        }
+
+        // muted (and soloed-out) channels don't feed the aux buses either
+        for channel in self
+            .channels
+            .iter()
+            .filter(|channel| channel.settings.is_audible(any_solo))
+        {
+            if let Some([left, right]) = channel.output(n_frames) {
+                mix_into([&mut *mix_left, &mut *mix_right], [left, right], 1.0);
+            }
+
+            for (aux_i, aux) in self.aux.iter_mut().enumerate() {
+                let level = channel.settings.sends[aux_i].level;
+
+                if level > 0.0
+                    && let Some(send) = channel.send_output(aux_i, n_frames)
+                {
+                    let [left, right] = &mut aux.buffers[0];
+                    mix_into([&mut left[..n_frames], &mut right[..n_frames]], send, level);
+                }
+            }
+        }
+
+        let channels = &self.channels;
+        let sidechain = |source: Sidechain| {
+            sidechain_input(
+                channels.get(source.channel).map(Box::as_ref),
+                source,
+                silence,
+                n_frames,
+            )
+        };
+
+        for aux in self.aux.iter_mut() {
+            let output_i = run_effects(&mut aux.effects, &mut aux.buffers, n_frames, sidechain);
+            let [left, right] = &aux.buffers[output_i];
+            mix_into(
+                [&mut *mix_left, &mut *mix_right],
+                [&left[..n_frames], &right[..n_frames]],
+                1.0,
+            );
+        }
+
+        // when no channel made any sound the master effects still get silence, so their tails
+        // ring out
+        let output_i = run_effects(&mut self.effects, &mut self.buffers, n_frames, sidechain);
+        let [left, right] = &mut self.buffers[output_i];
+        let (left, right) = (&mut left[..n_frames], &mut right[..n_frames]);
+
+        // the limiter goes last, so no master effect can push the output past its ceiling
+        let gain_reduction = self.master.process(left, right);
+        self.meters.gain_reduction.store(gain_reduction);
+        let (left, right) = (&*left, &*right);
+
+        self.meters.master.update(left, right, buffer_secs);
+
+        [left, right]
+    }
+}
+
+fn update_meter(meter: &Meter, output: Option<[&[Sample]; 2]>, buffer_secs: f32) {
+    match output {
+        Some([left, right]) => meter.update(left, right, buffer_secs),
+        None => meter.update(&[], &[], buffer_secs),
+    }
+}
+
+/// what an effect sidechained to source hears. silence if the source made no sound.
+fn sidechain_input<'a>(
+    channel: Option<&'a PluginChain>,
+    source: Sidechain,
+    silence: &'a [Sample],
+    n_frames: usize,
+) -> [&'a [Sample]; 2] {
+    channel
+        .and_then(|channel| channel.tap(source.pre_fader, n_frames))
+        .unwrap_or([silence, silence])
+}
+
+/// adds source, scaled by gain, onto mix.
+fn mix_into(mix: [&mut [Sample]; 2], source: [&[Sample]; 2], gain: f32) {
+    for (mix, source) in mix.into_iter().zip(source) {
+        mix.iter_mut()
+            .zip(source)
+            .for_each(|(mix, sample)| *mix += sample * gain);
+    }
+}
+
+/// drops whatever the audio thread hands back. returns once every engine is gone.
+pub fn janitor_thread(garbage: Receiver<Garbage>) {
+    for garbage in garbage {
+        match garbage {
+            Garbage::Plugin(plugin, _health) => {
+                debug!("dropping plugin {} off the audio thread", plugin.name())
+            }
+            Garbage::Channel(_channel) => debug!("dropping a removed channel off the audio thread"),
+            Garbage::Loans(_loans) => debug!("dropping the placeholders of lent plugins"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EffectChain, Loan, MixerCommand, MixerEngine, ParamTarget, QUEUE_LEN};
+    use crate::{
+        MAX_CHANNELS,
+        config::EngineConfig,
+        effects::NativeEffect,
+        master::{MasterSettings, Saturation},
+        meter::Meters,
+        mock::MockPlugin,
+        plugin_chain::{EffectSettings, PluginChain},
+        quarantine::PluginHealth,
+    };
+    use crossbeam::channel::{Sender, bounded};
+    use rack::prelude::*;
+    use std::sync::{Arc, atomic::Ordering};
+
+    /// an engine with one channel, played by a mock instrument.
+    fn mock_engine() -> (MixerEngine, Sender<MixerCommand>) {
+        let (commands, receiver) = bounded(QUEUE_LEN);
+        // nothing collects the garbage, so it's dropped in place
+        let (garbage, _) = bounded(1);
+        let engine = MixerEngine::new(
+            1,
+            EngineConfig::default(),
+            Arc::new(Meters::new(MAX_CHANNELS)),
+            receiver,
+            garbage,
+        );
+
+        commands
+            .send(MixerCommand::SetInstrument {
+                channel_i: 0,
+                sound_gen: Box::new(MockPlugin::new(0.25)),
+                health: Arc::default(),
+            })
+            .unwrap();
+
+        (engine, commands)
+    }
+
+    #[test]
+    fn mock_plugins_through_the_engine() {
+        let (mut engine, commands) = mock_engine();
+        let effect = MockPlugin::new(0.5);
+        let failing = effect.failing.clone();
+        let effect_health = Arc::new(PluginHealth::default());
+        let level = |engine: &mut MixerEngine| engine.process(64)[0].iter().sum::<f32>() / 64.0;
+
+        commands
+            .send(MixerCommand::AddEffect {
+                chain: EffectChain::Channel(0),
+                location: 0,
+                plugin: Box::new(effect),
+                health: effect_health.clone(),
+                settings: EffectSettings::default(),
+            })
+            .unwrap();
+        assert_eq!(level(&mut engine), 0.0);
+
+        commands
+            .send(MixerCommand::SendMidi {
+                channel_i: 0,
+                event: MidiEvent::note_on(60, 100, 0, 0),
+                frame: engine.frame(),
+            })
+            .unwrap();
+        let wet = level(&mut engine);
+        assert!(wet > 0.0);
+
+        // a failing effect is skipped, so the louder dry signal gets through
+        failing.store(true, Ordering::Relaxed);
+        assert!(level(&mut engine) > wet);
+
+        while !effect_health.is_quarantined() {
+            level(&mut engine);
+        }
+
+        commands
+            .send(MixerCommand::SendMidi {
+                channel_i: 0,
+                event: MidiEvent::note_off(60, 0, 0, 0),
+                frame: engine.frame(),
+            })
+            .unwrap();
+        assert_eq!(level(&mut engine), 0.0);
+    }
+
+    #[test]
+    fn midi_plays_on_its_frame() {
+        let (mut engine, commands) = mock_engine();
+        let on = engine.frame() + 64 + 10;
+        let off = on + 20;
+
+        for (event, frame) in [
+            (MidiEvent::note_on(60, 100, 0, 0), on),
+            (MidiEvent::note_off(60, 0, 0, 0), off),
+        ] {
+            commands
+                .send(MixerCommand::SendMidi {
+                    channel_i: 0,
+                    event,
+                    frame,
+                })
+                .unwrap();
+        }
+
+        assert!(engine.process(64)[0].iter().all(|sample| *sample == 0.0));
+
+        let [left, _] = engine.process(64);
+        assert!(left[..10].iter().all(|sample| *sample == 0.0));
+        assert!(left[10] != 0.0);
+        assert!(left[10..30].iter().all(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn limiter_comes_after_master_effects() {
+        let (mut engine, commands) = mock_engine();
+        let ceiling = 10.0f32.powf(-6.0 / 20.0);
+
+        commands
+            .send(MixerCommand::SetMasterSettings(MasterSettings::new(
+                Saturation::Limiter,
+                false,
+                -6.0,
+            )))
+            .unwrap();
+        // a master effect that turns the mix up well past the ceiling
+        commands
+            .send(MixerCommand::AddEffect {
+                chain: EffectChain::Master,
+                location: 0,
+                plugin: Box::new(MockPlugin::new(16.0)),
+                health: Arc::default(),
+                settings: EffectSettings::default(),
+            })
+            .unwrap();
+        commands
+            .send(MixerCommand::SendMidi {
+                channel_i: 0,
+                event: MidiEvent::note_on(60, 100, 0, 0),
+                frame: engine.frame(),
+            })
+            .unwrap();
+
+        let mut loudest = 0.0f32;
+
+        for _ in 0..8 {
+            let [left, right] = engine.process(64);
+            loudest = left
+                .iter()
+                .chain(right)
+                .fold(loudest, |peak, s| peak.max(s.abs()));
+        }
+
+        assert!(loudest > ceiling * 0.9);
+        assert!(loudest <= ceiling);
+    }
+
+    #[test]
+    fn parameters_set_through_the_engine() {
+        let (mut engine, commands) = mock_engine();
+        let level = |engine: &mut MixerEngine| engine.process(64)[0][63];
+
+        commands
+            .send(MixerCommand::SendMidi {
+                channel_i: 0,
+                event: MidiEvent::note_on(60, 100, 0, 0),
+                frame: engine.frame(),
+            })
+            .unwrap();
+        let before = level(&mut engine);
+        assert!(before > 0.0);
+
+        commands
+            .send(MixerCommand::SetParameter {
+                target: ParamTarget::Instrument(0),
+                index: 0,
+                value: 0.5,
+            })
+            .unwrap();
+        assert!(level(&mut engine) > before);
+
+        // parameters of slots that don't exist are ignored
+        commands
+            .send(MixerCommand::SetParameter {
+                target: ParamTarget::Effect(EffectChain::Master, 3),
+                index: 0,
+                value: 0.0,
+            })
+            .unwrap();
+        level(&mut engine);
+    }
+
+    #[test]
+    fn full_garbage_queue_holds_back_commands() {
+        let (commands, receiver) = bounded(QUEUE_LEN);
+        let (garbage, collected) = bounded(1);
+        let mut engine = MixerEngine::new(
+            1,
+            EngineConfig::default(),
+            Arc::new(Meters::new(MAX_CHANNELS)),
+            receiver,
+            garbage,
+        );
+
+        // every instrument after the first pushes the one before it out
+        for _ in 0..4 {
+            commands
+                .send(MixerCommand::SetInstrument {
+                    channel_i: 0,
+                    sound_gen: Box::new(MockPlugin::new(0.25)),
+                    health: Arc::default(),
+                })
+                .unwrap();
+        }
+
+        // one instrument fills the janitor's queue and the next waits on the engine, so the
+        // last command does too
+        engine.process(64);
+        assert_eq!(collected.len(), 1);
+        assert_eq!(commands.len(), 1);
+
+        let mut n_collected = 0;
+
+        while collected.try_recv().is_ok() {
+            n_collected += 1;
+            engine.process(64);
+        }
+
+        assert_eq!(n_collected, 3);
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn plugins_are_lent_out_and_returned() {
+        let (mut engine, commands) = mock_engine();
+        let level = |engine: &mut MixerEngine| engine.process(64)[0].iter().sum::<f32>() / 64.0;
+        let instrument = ParamTarget::Instrument(0);
+        let missing = ParamTarget::Effect(EffectChain::Master, 0);
+
+        commands
+            .send(MixerCommand::SendMidi {
+                channel_i: 0,
+                event: MidiEvent::note_on(60, 100, 0, 0),
+                frame: engine.frame(),
+            })
+            .unwrap();
+        let quiet = level(&mut engine);
+        assert!(quiet > 0.0);
+
+        let (reply, answer) = bounded(1);
+        commands
+            .send(MixerCommand::LendPlugins {
+                loans: vec![Loan::new(instrument), Loan::new(missing)],
+                reply,
+            })
+            .unwrap();
+        // the placeholder is silent while the instrument is away
+        assert_eq!(level(&mut engine), 0.0);
+
+        let mut loans = answer.try_recv().unwrap();
+        assert!(loans[0].lent);
+        assert_eq!(loans[0].plugin.name(), "Mock");
+        assert!(!loans[1].lent);
+        assert!(loans[0].plugin.set_parameter(0, 0.5));
+
+        commands.send(MixerCommand::ReturnPlugins(loans)).unwrap();
+        // it stopped its notes on the way out, so it comes back quiet
+        assert_eq!(level(&mut engine), 0.0);
+
+        commands
+            .send(MixerCommand::SendMidi {
+                channel_i: 0,
+                event: MidiEvent::note_on(60, 100, 0, 0),
+                frame: engine.frame(),
+            })
+            .unwrap();
+        let loud = level(&mut engine);
+        assert!(loud > quiet);
+
+        // nobody waits on this one, so the instrument goes straight back
+        let (reply, _) = bounded(1);
+        commands
+            .send(MixerCommand::LendPlugins {
+                loans: vec![Loan::new(instrument)],
+                reply,
+            })
+            .unwrap();
+        engine.process(64);
+        commands
+            .send(MixerCommand::SendMidi {
+                channel_i: 0,
+                event: MidiEvent::note_on(60, 100, 0, 0),
+                frame: engine.frame(),
+            })
+            .unwrap();
+        assert_eq!(level(&mut engine), loud);
+    }
+
+    #[test]
+    fn added_channels_fit_the_buffer_size() {
+        let (mut engine, commands) = mock_engine();
+        let buffer_frames = engine.config().buffer_frames;
+
+        // built before the engine switched to a bigger buffer
+        commands
+            .send(MixerCommand::AddChannel(Box::new(PluginChain::new(
+                buffer_frames / 4,
+            ))))
+            .unwrap();
+        engine.process(buffer_frames);
+        assert_eq!(engine.channels[1].buffer_frames(), buffer_frames);
+    }
+
+    #[test]
+    fn dry_signal_passes_fresh_delays_and_reverbs() {
+        for effect in [NativeEffect::Delay, NativeEffect::Reverb] {
+            let (mut engine, commands) = mock_engine();
+            // keeps the master saturation from bending the levels
+            commands
+                .send(MixerCommand::SetMasterSettings(MasterSettings::new(
+                    Saturation::Off,
+                    false,
+                    0.0,
+                )))
+                .unwrap();
+            commands
+                .send(MixerCommand::SendMidi {
+                    channel_i: 0,
+                    event: MidiEvent::note_on(60, 100, 0, 0),
+                    frame: engine.frame(),
+                })
+                .unwrap();
+            let dry = engine.process(64)[0][63];
+            assert!(dry > 0.0);
+
+            commands
+                .send(MixerCommand::AddEffect {
+                    chain: EffectChain::Channel(0),
+                    location: 0,
+                    plugin: effect.processor(engine.config()),
+                    health: Arc::default(),
+                    settings: effect.settings(),
+                })
+                .unwrap();
+
+            // the tail hasn't started yet, so only the dry half is heard
+            let level = engine.process(64)[0][63];
+            assert!(
+                (level - dry * 0.5).abs() < 1e-3,
+                "{effect:?}: {level} of {dry}"
+            );
+        }
+    }
+
+    #[test]
+    fn live_midi_is_dropped_while_it_is_off() {
+        let (mut engine, commands) = mock_engine();
+        let level = |engine: &mut MixerEngine| engine.process(64)[0][63];
+        engine.set_live_midi(false);
+
+        commands
+            .send(MixerCommand::SendMidi {
+                channel_i: 0,
+                event: MidiEvent::note_on(60, 100, 0, 0),
+                frame: engine.frame(),
+            })
+            .unwrap();
+        assert_eq!(level(&mut engine), 0.0);
+
+        // midi handed straight to the engine still plays
+        let frame = engine.frame();
+        engine.queue_midi(0, frame, MidiEvent::note_on(60, 100, 0, 0));
+        assert!(level(&mut engine) > 0.0);
+    }
+}
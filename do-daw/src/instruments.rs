@@ -0,0 +1,249 @@
+//! instruments that run natively, for when there are no VST3s around (or no need for one).
+
+use crate::{
+    config::EngineConfig,
+    dsp::bend_semitones,
+    native::Native,
+    traits::{GenSamples, ParamInfo, Processor},
+};
+use pyo3::prelude::*;
+use rack::{MidiEventKind, prelude::*};
+
+pub mod drum;
+pub mod fm;
+pub mod subtractive;
+
+/// voices of the polyphonic instruments.
+pub const N_VOICES: usize = 8;
+/// how loud one voice is at full velocity, leaves headroom for chords.
+const VOICE_GAIN: f32 = 0.25;
+/// the midi CC that stops every note a channel is playing.
+pub const ALL_NOTES_OFF: u8 = 123;
+const MOD_WHEEL: u8 = 1;
+
+/// the built-in instruments a channel can use in place of a plugin.
+#[pyclass(eq, eq_int, from_py_object)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NativeInstrument {
+    /// two detuned saws through an enveloped resonant low-pass.
+    Subtractive,
+    /// a two operator FM voice, good for bells, keys and basses.
+    Fm,
+    /// kick, snare and hats on the general midi drum notes.
+    Drums,
+}
+
+impl NativeInstrument {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Subtractive => "Subtractive Synth",
+            Self::Fm => "FM Synth",
+            Self::Drums => "Drum Synth",
+        }
+    }
+
+    /// categories in the same form VST3 plugins report theirs.
+    pub fn categories(&self) -> Vec<String> {
+        match self {
+            Self::Drums => vec!["Instrument".into(), "Drum".into()],
+            _ => vec!["Instrument".into(), "Synth".into()],
+        }
+    }
+
+    pub fn build(&self, sample_rate: usize) -> Box<dyn GenSamples> {
+        match self {
+            Self::Subtractive => Box::new(subtractive::Subtractive::new(sample_rate)),
+            Self::Fm => Box::new(fm::Fm::new(sample_rate)),
+            Self::Drums => Box::new(drum::Drums::new(sample_rate)),
+        }
+    }
+
+    /// builds the instrument, ready to go in a plugin chain.
+    pub fn processor(&self, config: EngineConfig) -> Box<dyn Processor> {
+        Box::new(Native::instrument(
+            self.name(),
+            self.categories(),
+            self.build(config.sample_rate),
+        ))
+    }
+}
+
+/// the knobs of a native instrument, listed by INFO and reached by the same index.
+trait SynthParams: Copy {
+    const INFO: &'static [ParamInfo];
+
+    fn value_mut(&mut self, index: usize) -> Option<&mut f32>;
+
+    fn get(&self, index: usize) -> Option<f32> {
+        let mut params = *self;
+
+        params.value_mut(index).map(|value| *value)
+    }
+
+    /// sets a parameter, clamped to its range.
+    fn set(&mut self, index: usize, value: f32) -> bool {
+        let Some(info) = Self::INFO.get(index) else {
+            return false;
+        };
+        let Some(current) = self.value_mut(index) else {
+            return false;
+        };
+
+        *current = value.clamp(info.min, info.max);
+
+        true
+    }
+}
+
+/// an envelope time parameter, in seconds.
+const fn time(name: &'static str, default: f32) -> ParamInfo {
+    ParamInfo {
+        name,
+        min: 0.001,
+        max: 5.0,
+        default,
+        unit: "s",
+    }
+}
+
+/// a parameter from 0.0 to 1.0, like an envelope's sustain.
+const fn level(name: &'static str, default: f32) -> ParamInfo {
+    ParamInfo {
+        name,
+        min: 0.0,
+        max: 1.0,
+        default,
+        unit: "",
+    }
+}
+
+/// the parts of midi the native instruments understand.
+enum Midi {
+    /// a note and its velocity from 0.0 to 1.0
+    NoteOn(u8, f32),
+    NoteOff(u8),
+    /// in semitones
+    Bend(f32),
+    /// from 0.0 to 1.0
+    ModWheel(f32),
+    AllNotesOff,
+}
+
+fn parse_midi(event: &MidiEvent) -> Option<Midi> {
+    match event.kind {
+        MidiEventKind::NoteOn {
+            note, velocity: 0, ..
+        } => Some(Midi::NoteOff(note)),
+        MidiEventKind::NoteOn { note, velocity, .. } => {
+            Some(Midi::NoteOn(note, velocity as f32 / 127.0))
+        }
+        MidiEventKind::NoteOff { note, .. } => Some(Midi::NoteOff(note)),
+        MidiEventKind::PitchBend { value, .. } => Some(Midi::Bend(bend_semitones(value))),
+        MidiEventKind::ControlChange {
+            controller: MOD_WHEEL,
+            value,
+            ..
+        } => Some(Midi::ModWheel(value as f32 / 127.0)),
+        MidiEventKind::ControlChange {
+            controller: ALL_NOTES_OFF,
+            ..
+        } => Some(Midi::AllNotesOff),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::NativeInstrument;
+    use crate::config::EngineConfig;
+    use rack::prelude::*;
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples
+            .iter()
+            .fold(0.0, |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn instruments_play_and_stop() {
+        let notes = [
+            (NativeInstrument::Subtractive, 60),
+            (NativeInstrument::Fm, 60),
+            (NativeInstrument::Drums, 36),
+            (NativeInstrument::Drums, 38),
+            (NativeInstrument::Drums, 42),
+        ];
+
+        for (instrument, note) in notes {
+            let mut synth = instrument.build(48000);
+            let mut buffer = vec![0.0; 512];
+
+            synth.fill_mono_buffer(&mut buffer, 512);
+            assert_eq!(
+                peak(&buffer),
+                0.0,
+                "{instrument:?} made sound without a note"
+            );
+
+            synth.handle_midi(&MidiEvent::note_on(note, 127, 0, 0));
+            synth.fill_mono_buffer(&mut buffer, 512);
+            let level = peak(&buffer);
+            assert!(
+                level > 0.05,
+                "{instrument:?} is too quiet on note {note}: {level}"
+            );
+            assert!(level <= 1.0, "{instrument:?} clips on note {note}: {level}");
+
+            // the release (or the drum's decay) rings out within two seconds
+            synth.handle_midi(&MidiEvent::note_off(note, 0, 0, 0));
+            (0..2 * 48000 / 512).for_each(|_| synth.fill_mono_buffer(&mut buffer, 512));
+            assert!(
+                peak(&buffer) < 1e-3,
+                "{instrument:?} didn't stop on note {note}"
+            );
+        }
+    }
+
+    #[test]
+    fn instrument_params_start_at_defaults_and_clamp() {
+        let instruments = [
+            NativeInstrument::Subtractive,
+            NativeInstrument::Fm,
+            NativeInstrument::Drums,
+        ];
+
+        for instrument in instruments {
+            let mut synth = instrument.processor(EngineConfig::default());
+            let params = synth.parameters();
+            assert!(!params.is_empty());
+
+            for param in &params {
+                assert_eq!(param.value, param.default, "{instrument:?} {}", param.name);
+                assert!(param.min <= param.default && param.default <= param.max);
+                assert!(synth.set_parameter(param.index, param.min - 1000.0));
+            }
+
+            for (param, after) in params.iter().zip(synth.parameters()) {
+                assert_eq!(after.value, param.min, "{instrument:?} {}", param.name);
+            }
+
+            assert!(!synth.set_parameter(params.len(), 0.0));
+        }
+    }
+
+    #[test]
+    fn instrument_state_restores_params() {
+        let mut synth = NativeInstrument::Subtractive.processor(EngineConfig::default());
+        let params = synth.parameters();
+        let state = synth.get_state().unwrap();
+
+        params
+            .iter()
+            .for_each(|param| assert!(synth.set_parameter(param.index, param.max)));
+        assert_ne!(synth.parameters(), params);
+
+        assert!(synth.set_state(&state));
+        assert_eq!(synth.parameters(), params);
+        assert!(!synth.set_state(&state[1..]));
+    }
+}
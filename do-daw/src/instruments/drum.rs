@@ -0,0 +1,219 @@
+use crate::{
+    Sample,
+    dsp::{Noise, Svf},
+    instruments::{Midi, SynthParams, parse_midi, time},
+    traits::{GenSamples, ParamInfo},
+};
+use rack::prelude::*;
+use std::f32::consts::TAU;
+
+/// how the drum synth sounds. decays are the time it takes a hit to fall to about a third.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrumParams {
+    /// where the kick's pitch sweep ends up, in Hz
+    pub kick_tune: f32,
+    pub kick_decay: f32,
+    /// the pitch of the snare's body, in Hz
+    pub snare_tune: f32,
+    pub snare_decay: f32,
+    pub hat_decay: f32,
+    pub open_hat_decay: f32,
+}
+
+impl Default for DrumParams {
+    fn default() -> Self {
+        Self {
+            kick_tune: 50.0,
+            kick_decay: 0.15,
+            snare_tune: 180.0,
+            snare_decay: 0.12,
+            hat_decay: 0.03,
+            open_hat_decay: 0.2,
+        }
+    }
+}
+
+impl SynthParams for DrumParams {
+    const INFO: &'static [ParamInfo] = &[
+        ParamInfo {
+            name: "Kick Tune",
+            min: 30.0,
+            max: 120.0,
+            default: 50.0,
+            unit: "Hz",
+        },
+        time("Kick Decay", 0.15),
+        ParamInfo {
+            name: "Snare Tune",
+            min: 100.0,
+            max: 400.0,
+            default: 180.0,
+            unit: "Hz",
+        },
+        time("Snare Decay", 0.12),
+        time("Hat Decay", 0.03),
+        time("Open Hat Decay", 0.2),
+    ];
+
+    fn value_mut(&mut self, index: usize) -> Option<&mut f32> {
+        match index {
+            0 => Some(&mut self.kick_tune),
+            1 => Some(&mut self.kick_decay),
+            2 => Some(&mut self.snare_tune),
+            3 => Some(&mut self.snare_decay),
+            4 => Some(&mut self.hat_decay),
+            5 => Some(&mut self.open_hat_decay),
+            _ => None,
+        }
+    }
+}
+
+/// the general midi notes each piece of the kit answers to.
+const KICK_NOTES: [u8; 2] = [35, 36];
+const SNARE_NOTES: [u8; 3] = [37, 38, 40];
+const CLOSED_HAT_NOTES: [u8; 2] = [42, 44];
+const OPEN_HAT_NOTE: u8 = 46;
+
+/// a decaying level, started by a hit.
+#[derive(Clone, Copy, Debug, Default)]
+struct Decay {
+    level: f32,
+    /// what level is multiplied by every sample
+    factor: f32,
+}
+
+impl Decay {
+    fn hit(&mut self, level: f32, secs: f32, sample_rate: f32) {
+        self.level = level;
+        self.factor = (-1.0 / (secs * sample_rate).max(1.0)).exp();
+    }
+
+    fn next(&mut self) -> f32 {
+        let level = self.level;
+        self.level *= self.factor;
+
+        if self.level < 1e-5 {
+            self.level = 0.0;
+        }
+
+        level
+    }
+
+    fn is_active(&self) -> bool {
+        self.level > 0.0
+    }
+}
+
+/// a synthesized kit of kick, snare and hats. every piece is a one-shot, note offs are ignored.
+/// a closed hat chokes the open one, like on a real hi-hat.
+pub struct Drums {
+    pub params: DrumParams,
+    noise: Noise,
+    kick_phase: f32,
+    kick_amp: Decay,
+    kick_pitch: Decay,
+    snare_phase: f32,
+    snare_body: Decay,
+    snare_noise: Decay,
+    snare_filter: Svf,
+    hat: Decay,
+    hat_filter: Svf,
+    sample_rate: f32,
+}
+
+impl Drums {
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            params: DrumParams::default(),
+            noise: Noise::default(),
+            kick_phase: 0.0,
+            kick_amp: Decay::default(),
+            kick_pitch: Decay::default(),
+            snare_phase: 0.0,
+            snare_body: Decay::default(),
+            snare_noise: Decay::default(),
+            snare_filter: Svf::default(),
+            hat: Decay::default(),
+            hat_filter: Svf::default(),
+            sample_rate: sample_rate as f32,
+        }
+    }
+}
+
+impl GenSamples for Drums {
+    fn fill_mono_buffer(&mut self, output_buffer: &mut [Sample], buffer_size: usize) {
+        let output = &mut output_buffer[..buffer_size];
+        let sample_rate = self.sample_rate;
+        output.fill(0.0);
+
+        if self.kick_amp.is_active() {
+            for sample in output.iter_mut() {
+                // sweeps down from four times the tune, which gives the click of the beater
+                let freq = self.params.kick_tune * (1.0 + 3.0 * self.kick_pitch.next());
+                *sample += (TAU * self.kick_phase).sin() * self.kick_amp.next();
+                self.kick_phase = (self.kick_phase + freq / sample_rate).fract();
+            }
+        }
+
+        if self.snare_body.is_active() || self.snare_noise.is_active() {
+            for sample in output.iter_mut() {
+                let body = (TAU * self.snare_phase).sin() * self.snare_body.next();
+                let [_, _, high] =
+                    self.snare_filter
+                        .run(self.noise.next_sample(), 1500.0, 0.0, sample_rate);
+                *sample += body * 0.5 + high * self.snare_noise.next() * 0.5;
+                self.snare_phase =
+                    (self.snare_phase + self.params.snare_tune / sample_rate).fract();
+            }
+        }
+
+        if self.hat.is_active() {
+            for sample in output.iter_mut() {
+                let [_, _, high] =
+                    self.hat_filter
+                        .run(self.noise.next_sample(), 7000.0, 0.3, sample_rate);
+                *sample += high * self.hat.next() * 0.4;
+            }
+        }
+    }
+
+    fn handle_midi(&mut self, event: &MidiEvent) {
+        let Some(Midi::NoteOn(note, velocity)) = parse_midi(event) else {
+            return;
+        };
+        let params = self.params;
+        let sample_rate = self.sample_rate;
+
+        if KICK_NOTES.contains(&note) {
+            self.kick_phase = 0.0;
+            self.kick_amp.hit(velocity, params.kick_decay, sample_rate);
+            self.kick_pitch.hit(1.0, 0.03, sample_rate);
+        } else if SNARE_NOTES.contains(&note) {
+            self.snare_phase = 0.0;
+            self.snare_body
+                .hit(velocity, params.snare_decay * 0.5, sample_rate);
+            self.snare_noise
+                .hit(velocity, params.snare_decay, sample_rate);
+        } else if CLOSED_HAT_NOTES.contains(&note) {
+            self.hat.hit(velocity, params.hat_decay, sample_rate);
+        } else if note == OPEN_HAT_NOTE {
+            self.hat.hit(velocity, params.open_hat_decay, sample_rate);
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        self.sample_rate = sample_rate as f32;
+    }
+
+    fn params(&self) -> &'static [ParamInfo] {
+        DrumParams::INFO
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<f32> {
+        self.params.get(index)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        self.params.set(index, value)
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{
+    Sample,
+    dsp::{Adsr, Envelope, VoiceAllocator, midi_to_hz},
+    instruments::{Midi, N_VOICES, SynthParams, VOICE_GAIN, level, parse_midi, time},
+    traits::{GenSamples, ParamInfo},
+};
+use rack::prelude::*;
+use std::f32::consts::TAU;
+
+/// how the FM synth sounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FmParams {
+    /// the modulator's frequency as a multiple of the note's
+    pub ratio: f32,
+    /// how hard the modulator drives the carrier's phase at the top of its envelope, in radians
+    pub index: f32,
+    /// how much of the modulator is fed back into itself, makes it brighter and noisier
+    pub feedback: f32,
+    pub amp: Adsr,
+    /// the envelope of the modulation index, shapes how the timbre changes over a note
+    pub modulation: Adsr,
+}
+
+impl Default for FmParams {
+    fn default() -> Self {
+        Self {
+            ratio: 2.0,
+            index: 3.0,
+            feedback: 0.1,
+            amp: Adsr {
+                attack: 0.002,
+                decay: 0.8,
+                sustain: 0.4,
+                release: 0.3,
+            },
+            modulation: Adsr {
+                attack: 0.002,
+                decay: 0.3,
+                sustain: 0.2,
+                release: 0.3,
+            },
+        }
+    }
+}
+
+impl SynthParams for FmParams {
+    const INFO: &'static [ParamInfo] = &[
+        ParamInfo {
+            name: "Ratio",
+            min: 0.25,
+            max: 16.0,
+            default: 2.0,
+            unit: "",
+        },
+        ParamInfo {
+            name: "Index",
+            min: 0.0,
+            max: 20.0,
+            default: 3.0,
+            unit: "",
+        },
+        level("Feedback", 0.1),
+        time("Amp Attack", 0.002),
+        time("Amp Decay", 0.8),
+        level("Amp Sustain", 0.4),
+        time("Amp Release", 0.3),
+        time("Mod Attack", 0.002),
+        time("Mod Decay", 0.3),
+        level("Mod Sustain", 0.2),
+        time("Mod Release", 0.3),
+    ];
+
+    fn value_mut(&mut self, index: usize) -> Option<&mut f32> {
+        match index {
+            0 => Some(&mut self.ratio),
+            1 => Some(&mut self.index),
+            2 => Some(&mut self.feedback),
+            3..7 => self.amp.value_mut(index - 3),
+            _ => self.modulation.value_mut(index.checked_sub(7)?),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Voice {
+    note: u8,
+    velocity: f32,
+    carrier: f32,
+    modulator: f32,
+    /// the modulator's last output, for the feedback
+    last_mod: f32,
+    amp: Envelope,
+    mod_env: Envelope,
+}
+
+/// a polyphonic two operator FM synth, one sine modulating another. the mod wheel adds to the
+/// modulation index.
+pub struct Fm {
+    pub params: FmParams,
+    voices: [Voice; N_VOICES],
+    allocator: VoiceAllocator<N_VOICES>,
+    /// pitch bend in semitones
+    bend: f32,
+    mod_wheel: f32,
+    sample_rate: f32,
+}
+
+impl Fm {
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            params: FmParams::default(),
+            voices: [Voice::default(); N_VOICES],
+            allocator: VoiceAllocator::default(),
+            bend: 0.0,
+            mod_wheel: 0.0,
+            sample_rate: sample_rate as f32,
+        }
+    }
+}
+
+impl GenSamples for Fm {
+    fn fill_mono_buffer(&mut self, output_buffer: &mut [Sample], buffer_size: usize) {
+        let output = &mut output_buffer[..buffer_size];
+        let params = &self.params;
+        let sample_rate = self.sample_rate;
+        let index = params.index * (1.0 + self.mod_wheel);
+        output.fill(0.0);
+
+        for voice in self.voices.iter_mut().filter(|voice| voice.amp.is_active()) {
+            let carrier_inc = midi_to_hz(voice.note as f32 + self.bend) / sample_rate;
+            let modulator_inc = carrier_inc * params.ratio;
+
+            for sample in output.iter_mut() {
+                let modulator = (TAU * voice.modulator + params.feedback * voice.last_mod).sin();
+                let depth = index * voice.mod_env.next(&params.modulation, sample_rate);
+                let carrier = (TAU * voice.carrier + depth * modulator).sin();
+
+                voice.last_mod = modulator;
+                voice.modulator = (voice.modulator + modulator_inc).fract();
+                voice.carrier = (voice.carrier + carrier_inc).fract();
+
+                *sample += carrier
+                    * voice.amp.next(&params.amp, sample_rate)
+                    * voice.velocity
+                    * VOICE_GAIN;
+            }
+        }
+    }
+
+    fn handle_midi(&mut self, event: &MidiEvent) {
+        match parse_midi(event) {
+            Some(Midi::NoteOn(note, velocity)) => {
+                let voices = &self.voices;
+                let voice_i = self
+                    .allocator
+                    .note_on(note, |voice| voices[voice].amp.is_active());
+                let voice = &mut self.voices[voice_i];
+
+                if !voice.amp.is_active() {
+                    voice.carrier = 0.0;
+                    voice.modulator = 0.0;
+                    voice.last_mod = 0.0;
+                }
+
+                voice.note = note;
+                voice.velocity = velocity;
+                voice.amp.trigger();
+                voice.mod_env.trigger();
+            }
+            Some(Midi::NoteOff(note)) => {
+                if let Some(voice) = self.allocator.note_off(note) {
+                    self.voices[voice].amp.release();
+                    self.voices[voice].mod_env.release();
+                }
+            }
+            Some(Midi::Bend(semitones)) => self.bend = semitones,
+            Some(Midi::ModWheel(value)) => self.mod_wheel = value,
+            Some(Midi::AllNotesOff) => {
+                self.allocator.clear();
+                self.voices.iter_mut().for_each(|voice| {
+                    voice.amp.release();
+                    voice.mod_env.release();
+                });
+            }
+            None => {}
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        self.sample_rate = sample_rate as f32;
+    }
+
+    fn params(&self) -> &'static [ParamInfo] {
+        FmParams::INFO
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<f32> {
+        self.params.get(index)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        self.params.set(index, value)
+    }
+}
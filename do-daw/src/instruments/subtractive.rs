@@ -0,0 +1,222 @@
+use crate::{
+    Sample,
+    dsp::{Adsr, Envelope, Svf, VoiceAllocator, midi_to_hz, saw},
+    instruments::{Midi, N_VOICES, SynthParams, VOICE_GAIN, level, parse_midi, time},
+    traits::{GenSamples, ParamInfo},
+};
+use rack::prelude::*;
+
+/// how the subtractive synth sounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SubtractiveParams {
+    /// how far the second saw is tuned up, in cents
+    pub detune: f32,
+    /// the filter's cutoff before the filter envelope and mod wheel, in Hz
+    pub cutoff: f32,
+    pub resonance: f32,
+    /// how far the filter envelope opens the cutoff, in octaves
+    pub env_amount: f32,
+    pub amp: Adsr,
+    pub filter: Adsr,
+}
+
+impl Default for SubtractiveParams {
+    fn default() -> Self {
+        Self {
+            detune: 7.0,
+            cutoff: 400.0,
+            resonance: 0.3,
+            env_amount: 4.0,
+            amp: Adsr {
+                attack: 0.005,
+                decay: 0.3,
+                sustain: 0.7,
+                release: 0.2,
+            },
+            filter: Adsr {
+                attack: 0.005,
+                decay: 0.4,
+                sustain: 0.2,
+                release: 0.2,
+            },
+        }
+    }
+}
+
+impl SynthParams for SubtractiveParams {
+    const INFO: &'static [ParamInfo] = &[
+        ParamInfo {
+            name: "Detune",
+            min: 0.0,
+            max: 50.0,
+            default: 7.0,
+            unit: "cents",
+        },
+        ParamInfo {
+            name: "Cutoff",
+            min: 20.0,
+            max: 20000.0,
+            default: 400.0,
+            unit: "Hz",
+        },
+        ParamInfo {
+            name: "Resonance",
+            min: 0.0,
+            max: 0.98,
+            default: 0.3,
+            unit: "",
+        },
+        ParamInfo {
+            name: "Env Amount",
+            min: 0.0,
+            max: 8.0,
+            default: 4.0,
+            unit: "oct",
+        },
+        time("Amp Attack", 0.005),
+        time("Amp Decay", 0.3),
+        level("Amp Sustain", 0.7),
+        time("Amp Release", 0.2),
+        time("Filter Attack", 0.005),
+        time("Filter Decay", 0.4),
+        level("Filter Sustain", 0.2),
+        time("Filter Release", 0.2),
+    ];
+
+    fn value_mut(&mut self, index: usize) -> Option<&mut f32> {
+        match index {
+            0 => Some(&mut self.detune),
+            1 => Some(&mut self.cutoff),
+            2 => Some(&mut self.resonance),
+            3 => Some(&mut self.env_amount),
+            4..8 => self.amp.value_mut(index - 4),
+            _ => self.filter.value_mut(index.checked_sub(8)?),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Voice {
+    note: u8,
+    velocity: f32,
+    phases: [f32; 2],
+    amp: Envelope,
+    filter_env: Envelope,
+    filter: Svf,
+}
+
+/// a polyphonic synth of two detuned saws through a resonant low-pass, each with its own
+/// envelope. the mod wheel opens the filter.
+pub struct Subtractive {
+    pub params: SubtractiveParams,
+    voices: [Voice; N_VOICES],
+    allocator: VoiceAllocator<N_VOICES>,
+    /// pitch bend in semitones
+    bend: f32,
+    mod_wheel: f32,
+    sample_rate: f32,
+}
+
+impl Subtractive {
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            params: SubtractiveParams::default(),
+            voices: [Voice::default(); N_VOICES],
+            allocator: VoiceAllocator::default(),
+            bend: 0.0,
+            mod_wheel: 0.0,
+            sample_rate: sample_rate as f32,
+        }
+    }
+}
+
+impl GenSamples for Subtractive {
+    fn fill_mono_buffer(&mut self, output_buffer: &mut [Sample], buffer_size: usize) {
+        let output = &mut output_buffer[..buffer_size];
+        let params = &self.params;
+        let sample_rate = self.sample_rate;
+        output.fill(0.0);
+
+        for voice in self.voices.iter_mut().filter(|voice| voice.amp.is_active()) {
+            let pitch = voice.note as f32 + self.bend;
+            let phase_incs = [
+                midi_to_hz(pitch) / sample_rate,
+                midi_to_hz(pitch + params.detune / 100.0) / sample_rate,
+            ];
+
+            for sample in output.iter_mut() {
+                let mut osc = 0.0;
+
+                for (phase, phase_inc) in voice.phases.iter_mut().zip(phase_incs) {
+                    osc += saw(*phase, phase_inc) * 0.5;
+                    *phase = (*phase + phase_inc).fract();
+                }
+
+                let octaves = params.env_amount
+                    * voice.filter_env.next(&params.filter, sample_rate)
+                    + self.mod_wheel * 2.0;
+                let cutoff = params.cutoff * 2.0f32.powf(octaves);
+                let [low, _, _] = voice.filter.run(osc, cutoff, params.resonance, sample_rate);
+
+                *sample +=
+                    low * voice.amp.next(&params.amp, sample_rate) * voice.velocity * VOICE_GAIN;
+            }
+        }
+    }
+
+    fn handle_midi(&mut self, event: &MidiEvent) {
+        match parse_midi(event) {
+            Some(Midi::NoteOn(note, velocity)) => {
+                let voices = &self.voices;
+                let voice_i = self
+                    .allocator
+                    .note_on(note, |voice| voices[voice].amp.is_active());
+                let voice = &mut self.voices[voice_i];
+
+                // a voice that has gone quiet starts fresh, a stolen one carries on from where it
+                // is so it doesn't click
+                if !voice.amp.is_active() {
+                    voice.phases = [0.0; 2];
+                    voice.filter.reset();
+                }
+
+                voice.note = note;
+                voice.velocity = velocity;
+                voice.amp.trigger();
+                voice.filter_env.trigger();
+            }
+            Some(Midi::NoteOff(note)) => {
+                if let Some(voice) = self.allocator.note_off(note) {
+                    self.voices[voice].amp.release();
+                    self.voices[voice].filter_env.release();
+                }
+            }
+            Some(Midi::Bend(semitones)) => self.bend = semitones,
+            Some(Midi::ModWheel(value)) => self.mod_wheel = value,
+            Some(Midi::AllNotesOff) => {
+                self.allocator.clear();
+                self.voices.iter_mut().for_each(|voice| {
+                    voice.amp.release();
+                    voice.filter_env.release();
+                });
+            }
+            None => {}
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        self.sample_rate = sample_rate as f32;
+    }
+
+    fn params(&self) -> &'static [ParamInfo] {
+        SubtractiveParams::INFO
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<f32> {
+        self.params.get(index)
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        self.params.set(index, value)
+    }
+}
@@ -1,35 +1,87 @@
 use crate::{
+    config::EngineConfig,
+    catalog::{PluginCatalog, PluginEntry},
     cursor::{Cursor, UiSector},
+    effects::NativeEffect,
+    instruments::NativeInstrument,
+    master::{MasterSettings, Saturation},
+    meter::MeterReading,
     mixer::Mixer,
+    output::{OutputKind, output_backend},
+    parameter::Parameter,
+    plugin_chain::PanLaw,
+    quarantine::PluginStatus,
+    state::{MixerState, PluginState},
     step_sequencer::{StepSequence, StepSequencer, StepState, audio_wrapper::AudioOutputWrapper},
 };
-use pyo3::prelude::*;
-use rack::vst3::Vst3Plugin;
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::path::PathBuf;
 
+pub mod catalog;
+pub mod config;
 pub mod cursor;
+pub mod dsp;
+pub mod effects;
+pub mod engine;
+pub mod instruments;
+pub mod master;
+pub mod meter;
+pub mod midi_queue;
 pub mod mixer;
+#[cfg(test)]
+pub mod mock;
+pub mod native;
+pub mod output;
+pub mod parameter;
 pub mod plugin_chain;
+pub mod quarantine;
+pub mod render;
+pub mod state;
 pub mod step_sequencer;
 pub mod traits;
+pub mod vst3;
 
+/// how many channels a mixer starts with, more can be added at runtime
 pub const N_CHANNELS: usize = 4;
+/// the most channels a mixer can have. the engine reserves room for this many up front
+pub const MAX_CHANNELS: usize = 16;
 pub const N_EFFECTS: usize = 3;
+/// aux send/return buses, enough for a shared reverb and delay
+pub const N_AUX: usize = 2;
 pub const N_SECTIONS: usize = 8;
+/// default sample rate, see config::EngineConfig
 pub const SAMPLE_RATE: usize = 48000;
+/// default buffer size, see config::EngineConfig
 pub const BUFFER_FRAMES: usize = 512;
 
-pub type SinglePlugin = Vst3Plugin;
 pub type Sample = f32;
+/// planar left/right pair of sample buffers, the unit every stage of the signal path passes on.
+pub type StereoBuffer = [Vec<Sample>; 2];
 
-/// Builds the Mixer, Step-Sequencer and makes threads for them where applicable
+/// Builds the Mixer, Step-Sequencer and makes threads for them where applicable. config picks the
+/// sample rate and buffer size, the defaults are SAMPLE_RATE and BUFFER_FRAMES. output picks where
+/// the audio goes, OutputKind.Wav also needs a wav_path. n_channels is how many channels the
+/// mixer starts with, at most MAX_CHANNELS. plugin_db is where the catalogue of installed plugins
+/// is kept, by default $XDG_DATA_HOME/dream-of-daw/plugins.db. it starts out empty, fill it with
+/// Mixer.rescan_plugins. raises a ValueError for OutputKind.Wav without a wav_path.
 #[pyfunction]
-fn run() -> (StepSequencer, Mixer, AudioOutputWrapper) {
+#[pyo3(signature = (config=None, output=OutputKind::Device, wav_path=None, n_channels=N_CHANNELS, plugin_db=None))]
+fn run(
+    config: Option<EngineConfig>,
+    output: OutputKind,
+    wav_path: Option<PathBuf>,
+    n_channels: usize,
+    plugin_db: Option<PathBuf>,
+) -> PyResult<(StepSequencer, Mixer, AudioOutputWrapper)> {
+    let backend = output_backend(output, wav_path)
+        .ok_or_else(|| PyValueError::new_err("OutputKind.Wav needs a wav_path"))?;
     env_logger::builder().format_timestamp(None).init();
-    let (mixer, dev) = Mixer::new();
-    let (stepper, jh) = StepSequencer::new(mixer.clone(), dev);
+    let catalog = PluginCatalog::open_or_in_memory(plugin_db);
+    let (mixer, dev) = Mixer::new(config.unwrap_or_default(), n_channels, backend.as_ref(), catalog);
+    let (stepper, jh) = StepSequencer::new(mixer.clone(), dev, backend);
 
     // TODO: return join handle seperately so step_sequencer can be sendable
-    (stepper, mixer, jh)
+    Ok((stepper, mixer, jh))
 }
 
 #[pyfunction]
@@ -50,6 +102,19 @@ fn midi_note(midi_note: usize) -> String {
 #[pymodule]
 fn do_daw(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Mixer>()?;
+    m.add_class::<EngineConfig>()?;
+    m.add_class::<OutputKind>()?;
+    m.add_class::<PanLaw>()?;
+    m.add_class::<PluginStatus>()?;
+    m.add_class::<Saturation>()?;
+    m.add_class::<MasterSettings>()?;
+    m.add_class::<MeterReading>()?;
+    m.add_class::<NativeInstrument>()?;
+    m.add_class::<NativeEffect>()?;
+    m.add_class::<Parameter>()?;
+    m.add_class::<PluginState>()?;
+    m.add_class::<MixerState>()?;
+    m.add_class::<PluginEntry>()?;
     m.add_class::<AudioOutputWrapper>()?;
     m.add_class::<StepSequencer>()?;
     m.add_class::<StepSequence>()?;
@@ -61,6 +126,7 @@ fn do_daw(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(midi_note, m)?)?;
 
     m.add("N_CHANNELS", N_CHANNELS)?;
+    m.add("MAX_CHANNELS", MAX_CHANNELS)?;
     m.add("N_EFFECTS", N_EFFECTS)?;
     m.add("N_SECTIONS", N_SECTIONS)?;
 
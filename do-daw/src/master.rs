@@ -0,0 +1,314 @@
+use crate::{Sample, effects::FLAT};
+use biquad::*;
+use log::*;
+use pyo3::prelude::*;
+
+/// how far ahead the limiter looks for peaks. this is also the latency it adds.
+pub const LIMITER_LOOKAHEAD_SECS: f32 = 0.0015;
+/// how long the limiter takes to let go once a peak has passed.
+pub const LIMITER_RELEASE_SECS: f32 = 0.05;
+
+/// what the master bus does to keep the mix from clipping.
+#[pyclass(eq, eq_int, from_py_object)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Saturation {
+    /// the mix goes out untouched.
+    Off,
+    /// an all-pass followed by tanh, the way the master bus has always sounded.
+    #[default]
+    SoftClip,
+    /// a lookahead brickwall limiter, nothing gets past the ceiling.
+    Limiter,
+}
+
+#[pyclass(get_all, set_all, from_py_object)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MasterSettings {
+    pub saturation: Saturation,
+    /// runs the soft clipper at twice the sample rate to cut down on aliasing.
+    pub oversample: bool,
+    /// the limiter's ceiling in dBFS.
+    pub ceiling_db: f32,
+}
+
+impl Default for MasterSettings {
+    fn default() -> Self {
+        Self {
+            saturation: Saturation::default(),
+            oversample: false,
+            ceiling_db: -0.3,
+        }
+    }
+}
+
+#[pymethods]
+impl MasterSettings {
+    #[new]
+    #[pyo3(signature = (saturation=Saturation::SoftClip, oversample=false, ceiling_db=-0.3))]
+    pub fn new(saturation: Saturation, oversample: bool, ceiling_db: f32) -> Self {
+        Self {
+            saturation,
+            oversample,
+            ceiling_db,
+        }
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "MasterSettings(saturation={:?}, oversample={}, ceiling_db={})",
+            self.saturation, self.oversample, self.ceiling_db
+        )
+    }
+}
+
+/// the all-pass filter every side of the master bus runs through before it's soft clipped.
+fn master_allpass(sample_rate: usize) -> DirectForm1<f32> {
+    // the middle of the audible range, kept under nyquist at low sample rates
+    let f0 = (((20_000 + 20) / 2) as f32).min(sample_rate as f32 * 0.45);
+    let coeffs = Coefficients::<f32>::from_params(
+        Type::AllPass,
+        sample_rate.hz(),
+        f0.hz(),
+        Q_BUTTERWORTH_F32,
+    )
+    .unwrap_or_else(|e| {
+        warn!("the master all-pass can't run at {sample_rate} Hz, it's left out. {e:?}");
+        FLAT
+    });
+
+    DirectForm1::<f32>::new(coeffs)
+}
+
+/// 2x oversampled tanh for one side of the bus. zero-stuffs, filters, clips, filters and drops
+/// every other sample. each filter is two cascaded butterworth low-passes.
+struct Oversampler {
+    up: [DirectForm1<f32>; 2],
+    down: [DirectForm1<f32>; 2],
+}
+
+impl Oversampler {
+    fn new(sample_rate: usize) -> Self {
+        let f0 = (sample_rate as f32 * 0.45).min(20_000.0).hz();
+        let fs = (sample_rate * 2).hz();
+        let coeffs = Coefficients::<f32>::from_params(Type::LowPass, fs, f0, Q_BUTTERWORTH_F32)
+            .unwrap_or_else(|e| {
+                warn!("the oversampler's filters can't run at {sample_rate} Hz. {e:?}");
+                FLAT
+            });
+        let filter = DirectForm1::<f32>::new(coeffs);
+
+        Self {
+            up: [filter; 2],
+            down: [filter; 2],
+        }
+    }
+
+    fn tanh(&mut self, sample: Sample) -> Sample {
+        let mut out = 0.0;
+
+        // zero stuffing halves the level, so the real sample is doubled to make up for it
+        for sample in [sample * 2.0, 0.0] {
+            let up = self.up[0].run(sample);
+            let up = self.up[1].run(up);
+            let down = self.down[0].run(up.tanh());
+            out = self.down[1].run(down);
+        }
+
+        out
+    }
+}
+
+/// stereo lookahead brickwall limiter. the gain needed to keep each frame under the ceiling is
+/// held for the length of the lookahead, released and then smoothed with a moving average of the
+/// same length, so it has fully come down by the time the (delayed) peak goes out.
+struct Limiter {
+    /// the delayed audio, a ring of `lookahead` frames
+    delay: [Vec<Sample>; 2],
+    /// the gain each of the last `lookahead + 1` frames needed
+    needed: Vec<f32>,
+    needed_i: usize,
+    /// the released gain over the last `lookahead` frames, averaged into the applied gain
+    smoothing: Vec<f32>,
+    smoothing_sum: f32,
+    i: usize,
+    gain: f32,
+    release: f32,
+}
+
+impl Limiter {
+    fn new(sample_rate: usize) -> Self {
+        let lookahead = ((sample_rate as f32 * LIMITER_LOOKAHEAD_SECS) as usize).max(1);
+
+        Self {
+            delay: [vec![0.0; lookahead], vec![0.0; lookahead]],
+            needed: vec![1.0; lookahead + 1],
+            needed_i: 0,
+            smoothing: vec![1.0; lookahead],
+            smoothing_sum: lookahead as f32,
+            i: 0,
+            gain: 1.0,
+            release: 1.0 - (-1.0 / (LIMITER_RELEASE_SECS * sample_rate as f32)).exp(),
+        }
+    }
+
+    /// limits the buffer in place and returns the lowest gain it applied.
+    fn process(&mut self, left: &mut [Sample], right: &mut [Sample], ceiling: f32) -> f32 {
+        let lookahead = self.smoothing.len();
+        let mut min_gain = 1.0f32;
+
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            let peak = left.abs().max(right.abs());
+            self.needed[self.needed_i] = if peak > ceiling { ceiling / peak } else { 1.0 };
+            self.needed_i = (self.needed_i + 1) % self.needed.len();
+
+            let held = self.needed.iter().copied().fold(1.0, f32::min);
+            self.gain = held.min(self.gain + (1.0 - self.gain) * self.release);
+
+            self.smoothing_sum += self.gain - self.smoothing[self.i];
+            self.smoothing[self.i] = self.gain;
+            let gain = (self.smoothing_sum / lookahead as f32).min(1.0);
+            min_gain = min_gain.min(gain);
+
+            let (in_left, in_right) = (*left, *right);
+            // rounding in the average can leave the odd sample a hair over, so clamp as well
+            *left = (self.delay[0][self.i] * gain).clamp(-ceiling, ceiling);
+            *right = (self.delay[1][self.i] * gain).clamp(-ceiling, ceiling);
+            self.delay[0][self.i] = in_left;
+            self.delay[1][self.i] = in_right;
+
+            self.i += 1;
+
+            if self.i == lookahead {
+                self.i = 0;
+                // keeps float error from piling up in the running sum
+                self.smoothing_sum = self.smoothing.iter().sum();
+            }
+        }
+
+        min_gain
+    }
+}
+
+/// the last stage of the master bus, after the master effects and right before the output.
+pub struct MasterStage {
+    settings: MasterSettings,
+    allpass: [DirectForm1<f32>; 2],
+    oversamplers: [Oversampler; 2],
+    limiter: Limiter,
+}
+
+impl MasterStage {
+    /// allocates the limiter's buffers, so this is kept off the audio thread.
+    pub fn new(settings: MasterSettings, sample_rate: usize) -> Self {
+        Self {
+            settings,
+            allpass: [master_allpass(sample_rate), master_allpass(sample_rate)],
+            oversamplers: [Oversampler::new(sample_rate), Oversampler::new(sample_rate)],
+            limiter: Limiter::new(sample_rate),
+        }
+    }
+
+    pub fn settings(&self) -> MasterSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: MasterSettings) {
+        self.settings = settings;
+    }
+
+    /// processes the mix in place and returns how much it was turned down, in dB.
+    pub fn process(&mut self, left: &mut [Sample], right: &mut [Sample]) -> f32 {
+        let min_gain = match self.settings.saturation {
+            Saturation::Off => 1.0,
+            Saturation::SoftClip => {
+                let peak_in = peak(left).max(peak(right));
+
+                for (side, samples) in [&mut *left, &mut *right].into_iter().enumerate() {
+                    let allpass = &mut self.allpass[side];
+                    let oversampler = &mut self.oversamplers[side];
+
+                    if self.settings.oversample {
+                        samples
+                            .iter_mut()
+                            .for_each(|sample| *sample = oversampler.tanh(allpass.run(*sample)));
+                    } else {
+                        samples
+                            .iter_mut()
+                            .for_each(|sample| *sample = allpass.run(*sample).tanh());
+                    }
+                }
+
+                // tanh is always a touch under its input, so quiet passages aren't reported
+                if peak_in > 0.5 {
+                    (peak(left).max(peak(right)) / peak_in).min(1.0)
+                } else {
+                    1.0
+                }
+            }
+            Saturation::Limiter => {
+                let ceiling = 10.0f32.powf(self.settings.ceiling_db.min(0.0) / 20.0);
+
+                self.limiter.process(left, right, ceiling)
+            }
+        };
+
+        -20.0 * min_gain.max(1e-6).log10()
+    }
+}
+
+fn peak(samples: &[Sample]) -> f32 {
+    samples
+        .iter()
+        .fold(0.0, |peak, sample| peak.max(sample.abs()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MasterSettings, MasterStage, Saturation};
+
+    #[test]
+    fn limiter_holds_the_ceiling() {
+        let settings = MasterSettings::new(Saturation::Limiter, false, -6.0);
+        let ceiling = 10.0f32.powf(-6.0 / 20.0);
+        let mut stage = MasterStage::new(settings, 48000);
+        let mut max_gr = 0.0f32;
+
+        for buffer in 0..16 {
+            // quiet, then a few buffers of four stacked synths, then quiet again
+            let level = if (4..8).contains(&buffer) { 4.0 } else { 0.1 };
+            let mut left: Vec<f32> = (0..256).map(|i| level * (i as f32 * 0.05).sin()).collect();
+            let mut right = left.iter().map(|s| -s).collect::<Vec<_>>();
+
+            max_gr = max_gr.max(stage.process(&mut left, &mut right));
+            assert!(left.iter().chain(&right).all(|s| s.abs() <= ceiling));
+        }
+
+        assert!(max_gr > 6.0);
+
+        // the release lets go once the loud part is over
+        let mut left = vec![0.1; 48000];
+        let mut right = vec![0.1; 48000];
+        stage.process(&mut left, &mut right);
+        assert!(stage.process(&mut left[..256], &mut right[..256]) < 0.01);
+    }
+
+    #[test]
+    fn low_sample_rates() {
+        for sample_rate in [8000, 11025, 16000, 22050] {
+            for oversample in [false, true] {
+                let settings = MasterSettings::new(Saturation::SoftClip, oversample, -0.3);
+                let mut stage = MasterStage::new(settings, sample_rate);
+                let mut left: Vec<f32> = (0..256).map(|i| 2.0 * (i as f32 * 0.05).sin()).collect();
+                let mut right = left.clone();
+
+                stage.process(&mut left, &mut right);
+                assert!(
+                    left.iter()
+                        .chain(&right)
+                        .all(|s| s.is_finite() && s.abs() <= 1.0),
+                    "{sample_rate} Hz"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::Sample;
+use pyo3::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// how long (in seconds) the peak-hold indicator stays put before it falls back to the peak.
+pub const PEAK_HOLD_SECS: f32 = 1.5;
+/// how fast the peak indicator falls once the signal gets quieter.
+pub const PEAK_DECAY_DB_PER_SEC: f32 = 24.0;
+
+/// an f32 that the audio thread can publish and the UI can read without taking a lock.
+#[derive(Debug, Default)]
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// a snapshot of a meter, as handed to python. every pair is (left, right) and levels are linear
+/// gain where 1.0 is full scale.
+#[pyclass(get_all, from_py_object)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeterReading {
+    pub peak: (f32, f32),
+    pub rms: (f32, f32),
+    pub peak_hold: (f32, f32),
+    /// latched once a side reaches full scale, cleared with Mixer.clear_clips
+    pub clip: (bool, bool),
+}
+
+/// the level of one side of a stereo signal.
+#[derive(Debug, Default)]
+struct SideMeter {
+    peak: AtomicF32,
+    rms: AtomicF32,
+    peak_hold: AtomicF32,
+    /// seconds since peak_hold was last set
+    hold_age: AtomicF32,
+    clip: AtomicBool,
+}
+
+impl SideMeter {
+    fn update(&self, samples: &[Sample], buffer_secs: f32) {
+        let (rms, peak) = analyze_buffer(samples);
+        let decay = 10.0f32.powf(-PEAK_DECAY_DB_PER_SEC * buffer_secs / 20.0);
+        let peak_display = peak.max(self.peak.load() * decay);
+        let hold_age = self.hold_age.load() + buffer_secs;
+
+        self.rms.store(rms);
+        self.peak.store(peak_display);
+
+        if peak >= self.peak_hold.load() || hold_age > PEAK_HOLD_SECS {
+            self.peak_hold.store(peak_display);
+            self.hold_age.store(0.0);
+        } else {
+            self.hold_age.store(hold_age);
+        }
+
+        if peak >= 1.0 {
+            self.clip.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn copy_from(&self, other: &Self) {
+        self.peak.store(other.peak.load());
+        self.rms.store(other.rms.load());
+        self.peak_hold.store(other.peak_hold.load());
+        self.hold_age.store(other.hold_age.load());
+        self.clip
+            .store(other.clip.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+/// lock-free stereo level meter. only the audio thread should call update.
+#[derive(Debug, Default)]
+pub struct Meter {
+    left: SideMeter,
+    right: SideMeter,
+}
+
+impl Meter {
+    /// feeds one buffer through the meter. buffer_secs is the length of the buffer in seconds and
+    /// drives the peak decay and hold timing.
+    pub fn update(&self, left: &[Sample], right: &[Sample], buffer_secs: f32) {
+        self.left.update(left, buffer_secs);
+        self.right.update(right, buffer_secs);
+    }
+
+    pub fn reading(&self) -> MeterReading {
+        MeterReading {
+            peak: (self.left.peak.load(), self.right.peak.load()),
+            rms: (self.left.rms.load(), self.right.rms.load()),
+            peak_hold: (self.left.peak_hold.load(), self.right.peak_hold.load()),
+            clip: (
+                self.left.clip.load(Ordering::Relaxed),
+                self.right.clip.load(Ordering::Relaxed),
+            ),
+        }
+    }
+
+    pub fn clear_clip(&self) {
+        self.left.clip.store(false, Ordering::Relaxed);
+        self.right.clip.store(false, Ordering::Relaxed);
+    }
+
+    /// takes over the levels, peak-hold and clip of other.
+    fn copy_from(&self, other: &Self) {
+        self.left.copy_from(&other.left);
+        self.right.copy_from(&other.right);
+    }
+}
+
+/// the meters of every mixer channel and of the master bus.
+#[derive(Debug)]
+pub struct Meters {
+    pub channels: Vec<Meter>,
+    pub master: Meter,
+    /// how far the master saturation/limiter turned the last buffer down, in dB
+    pub gain_reduction: AtomicF32,
+}
+
+impl Meters {
+    pub fn new(n_channels: usize) -> Self {
+        Self {
+            channels: (0..n_channels).map(|_| Meter::default()).collect(),
+            master: Meter::default(),
+            gain_reduction: AtomicF32::default(),
+        }
+    }
+
+    /// moves the meters after channel_i down by one, the way the channels move when it's removed,
+    /// and silences the last one. doesn't allocate.
+    pub fn channel_removed(&self, channel_i: usize) {
+        let Some(moved) = self.channels.get(channel_i..) else {
+            return;
+        };
+
+        for pair in moved.windows(2) {
+            pair[0].copy_from(&pair[1]);
+        }
+
+        if let Some(last) = moved.last() {
+            last.copy_from(&Meter::default());
+        }
+    }
+}
+
+/// Calculate RMS and peak levels for a single channel of audio
+pub fn analyze_buffer(audio: &[f32]) -> (f32, f32) {
+    if audio.is_empty() {
+        return (0.0, 0.0);
+    }
+
+    let frames = audio.len();
+
+    let mut sum = 0.0f32;
+    let mut peak = 0.0f32;
+
+    for sample in audio {
+        sum += sample * sample;
+
+        peak = peak.max(sample.abs());
+    }
+
+    let rms = (sum / frames as f32).sqrt();
+
+    (rms, peak)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Meter, Meters, PEAK_HOLD_SECS, analyze_buffer};
+
+    #[test]
+    fn peak_hold_and_clip() {
+        let meter = Meter::default();
+        let loud = [0.0, 1.0, -0.5, 0.0];
+        let quiet = [0.0; 4];
+
+        let (rms, peak) = analyze_buffer(&loud);
+        assert!((rms - 0.559017).abs() < 1e-6);
+        assert_eq!(peak, 1.0);
+
+        meter.update(&loud, &quiet, 0.01);
+        let reading = meter.reading();
+        assert_eq!(reading.peak, (1.0, 0.0));
+        assert_eq!(reading.clip, (true, false));
+
+        // peak decays, the hold doesn't until PEAK_HOLD_SECS have passed
+        meter.update(&quiet, &quiet, 0.1);
+        let reading = meter.reading();
+        assert!(reading.peak.0 < 1.0);
+        assert_eq!(reading.peak_hold.0, 1.0);
+
+        meter.update(&quiet, &quiet, PEAK_HOLD_SECS);
+        assert!(meter.reading().peak_hold.0 < 1.0);
+
+        meter.clear_clip();
+        assert_eq!(meter.reading().clip, (false, false));
+    }
+
+    #[test]
+    fn meters_move_down_with_removed_channels() {
+        let meters = Meters::new(3);
+        let clipped = [1.0; 4];
+        let quiet = [0.25; 4];
+
+        meters.channels[0].update(&clipped, &clipped, 0.01);
+        meters.channels[1].update(&clipped, &clipped, 0.01);
+        meters.channels[2].update(&quiet, &quiet, 0.01);
+        let third = meters.channels[2].reading();
+
+        meters.channel_removed(1);
+        assert_eq!(meters.channels[0].reading().clip, (true, true));
+        // the channel that moved into the removed one's place keeps its own readings
+        assert_eq!(meters.channels[1].reading(), third);
+        assert_eq!(meters.channels[2].reading(), Default::default());
+    }
+}
@@ -0,0 +1,52 @@
+//! midi events waiting for the frame they should play at.
+
+use rack::prelude::MidiEvent;
+
+/// how many events a queue holds before pushing starts to fail.
+pub const MIDI_QUEUE_LEN: usize = 256;
+
+/// midi events in the order of the frame they should play at. the room for them is allocated up
+/// front, so the audio thread can push and pop without allocating.
+#[derive(Debug)]
+pub struct MidiQueue {
+    events: Vec<(u64, MidiEvent)>,
+}
+
+impl Default for MidiQueue {
+    fn default() -> Self {
+        Self {
+            events: Vec::with_capacity(MIDI_QUEUE_LEN),
+        }
+    }
+}
+
+impl MidiQueue {
+    /// queues event to play at frame, after any already queued for the same frame. hands the
+    /// event back when the queue is full.
+    pub fn push(&mut self, frame: u64, event: MidiEvent) -> Result<(), MidiEvent> {
+        if self.events.len() >= MIDI_QUEUE_LEN {
+            return Err(event);
+        }
+
+        let i = self.events.partition_point(|(queued, _)| *queued <= frame);
+        self.events.insert(i, (frame, event));
+
+        Ok(())
+    }
+
+    /// takes out every event that should play before frame end, in order.
+    pub fn pop_before(&mut self, end: u64) -> impl Iterator<Item = (u64, MidiEvent)> + '_ {
+        let n = self.events.partition_point(|(queued, _)| *queued < end);
+
+        self.events.drain(..n)
+    }
+
+    /// drops every queued event, keeping the room for them.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
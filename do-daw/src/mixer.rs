@@ -1,39 +1,141 @@
-use crate::plugin_chain::PluginChain;
-use crate::{Sample, SinglePlugin, BUFFER_FRAMES, N_CHANNELS, N_EFFECTS, SAMPLE_RATE};
+use crate::catalog::{PluginCatalog, PluginEntry, PluginError};
+use crate::config::EngineConfig;
+use crate::effects::NativeEffect;
+use crate::engine::{
+    EffectChain, EngineClock, Garbage, Loan, MixerCommand, MixerEngine, ParamTarget, QUEUE_LEN,
+    janitor_thread,
+};
+use crate::instruments::NativeInstrument;
+use crate::master::MasterSettings;
+use crate::meter::{MeterReading, Meters};
+use crate::output::{OutputBackend, OutputHandle};
+use crate::parameter::Parameter;
+use crate::plugin_chain::{
+    AuxSend, ChannelSettings, EffectSettings, PanLaw, PluginChain, Sidechain, move_slot,
+};
+use crate::quarantine::{PluginHealth, PluginStatus};
+use crate::state::{MixerState, PluginState};
+use crate::traits::Processor;
+use crate::vst3::Vst3;
+use crate::{MAX_CHANNELS, N_AUX, N_EFFECTS};
+use crossbeam::channel::{Sender, bounded};
 use log::*;
+use midi_msg::*;
 use midir::{Ignore, MidiInput};
-use pyo3::prelude::*;
-use rack::prelude::*;
-use rayon::{current_num_threads, prelude::*};
+use pyo3::{
+    exceptions::{PyIndexError, PyRuntimeError},
+    prelude::*,
+};
+use rack::prelude::{MidiEvent, Scanner};
 use std::thread::sleep;
 use std::time::Duration;
 use std::{
-    sync::{atomic::{AtomicUsize, Ordering}, {Arc, RwLock}},
-thread::{JoinHandle, spawn},
-path::PathBuf,
-collections::HashMap,
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        {Arc, Mutex, RwLock},
+    },
+    thread::{JoinHandle, spawn},
 };
-use tinyaudio::{run_output_device, OutputDevice, OutputDeviceParameters};
-use biquad::*;
-use midi_msg::*;
 
-struct Multizip<T>(Vec<T>);
+/// how long to wait for the audio thread to answer a request, it's a few buffers unless it stalled.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// what an effect slot looks like from outside the audio thread.
+#[derive(Clone, Debug)]
+pub struct EffectView {
+    /// the name of the effect plugin or native effect
+    pub name: String,
+    /// shared with the audio thread
+    pub health: Arc<PluginHealth>,
+    pub settings: EffectSettings,
+    /// the effect's parameters, as last set from here
+    pub params: Vec<Parameter>,
+}
 
-impl<T> Iterator for Multizip<T>
-where
-    T: Iterator,
-{
-    type Item = Vec<T::Item>;
+/// what a channel looks like from outside the audio thread.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelView {
+    pub settings: ChannelSettings,
+    /// the name of the instrument plugin or native instrument
+    pub instrument: Option<String>,
+    /// shared with the audio thread
+    pub instrument_health: Arc<PluginHealth>,
+    /// categories of the instrument plugin
+    pub categories: Vec<String>,
+    /// the instrument's parameters, as last set from here
+    pub instrument_params: Vec<Parameter>,
+    pub effects: Vec<EffectView>,
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.0.is_empty() {
-            return None;
+/// a copy of the engines state that the UI, sequencer and midi threads can read without
+/// bothering the audio thread. the Mixer methods keep it in step with the commands they send.
+#[derive(Clone, Debug, Default)]
+pub struct MixerView {
+    pub channels: Vec<ChannelView>,
+    /// the master effects
+    pub effects: Vec<EffectView>,
+    pub pan_law: PanLaw,
+    pub master: MasterSettings,
+    /// the effects of every aux bus
+    pub aux_effects: Vec<Vec<EffectView>>,
+}
+
+impl MixerView {
+    pub fn effects(&self, chain: EffectChain) -> Option<&Vec<EffectView>> {
+        match chain {
+            EffectChain::Channel(channel_i) => {
+                self.channels.get(channel_i).map(|chan| &chan.effects)
+            }
+            EffectChain::Aux(aux) => self.aux_effects.get(aux),
+            EffectChain::Master => Some(&self.effects),
+        }
+    }
+
+    pub fn effects_mut(&mut self, chain: EffectChain) -> Option<&mut Vec<EffectView>> {
+        match chain {
+            EffectChain::Channel(channel_i) => self
+                .channels
+                .get_mut(channel_i)
+                .map(|chan| &mut chan.effects),
+            EffectChain::Aux(aux) => self.aux_effects.get_mut(aux),
+            EffectChain::Master => Some(&mut self.effects),
         }
+    }
 
-        let item: Vec<Option<T::Item>> = self.0.iter_mut().map(|iter| iter.next()).collect();
-        let item: Option<Vec<T::Item>> = item.into_iter().collect();
+    pub fn params(&self, target: ParamTarget) -> Option<&Vec<Parameter>> {
+        match target {
+            ParamTarget::Instrument(channel_i) => self
+                .channels
+                .get(channel_i)
+                .map(|chan| &chan.instrument_params),
+            ParamTarget::Effect(chain, effect) => {
+                self.effects(chain)?.get(effect).map(|slot| &slot.params)
+            }
+        }
+    }
+
+    pub fn params_mut(&mut self, target: ParamTarget) -> Option<&mut Vec<Parameter>> {
+        match target {
+            ParamTarget::Instrument(channel_i) => self
+                .channels
+                .get_mut(channel_i)
+                .map(|chan| &mut chan.instrument_params),
+            ParamTarget::Effect(chain, effect) => self
+                .effects_mut(chain)?
+                .get_mut(effect)
+                .map(|slot| &mut slot.params),
+        }
+    }
 
-        item
+    /// every effect slot of every chain.
+    fn all_effects_mut(&mut self) -> impl Iterator<Item = &mut EffectView> {
+        self.channels
+            .iter_mut()
+            .flat_map(|channel| channel.effects.iter_mut())
+            .chain(self.effects.iter_mut())
+            .chain(self.aux_effects.iter_mut().flatten())
     }
 }
 
@@ -44,10 +146,20 @@ pub struct Mixer {
     // send: Sender<()>,
     // recv: Receiver<Vec<u8>>,
     // _device: OutputDevice,
-    pub channels: Arc<Vec<Arc<RwLock<PluginChain>>>>,
-    /// global effects on the output of all channels. these get applied after the channels are
-    /// mixed together.
-    pub effects: Arc<RwLock<Vec<SinglePlugin>>>,
+    /// the only way to change the audio threads state.
+    pub commands: Sender<MixerCommand>,
+    pub view: Arc<RwLock<MixerView>>,
+    /// per-channel and master levels, published by the audio thread.
+    pub meters: Arc<Meters>,
+    /// the live output only ever try_locks this, an offline render holds it for the whole render
+    /// and the live output plays silence in the mean time.
+    pub engine: Arc<Mutex<MixerEngine>>,
+    /// the engine's clock, midi is stamped with the frame it should play at by this
+    pub clock: Arc<EngineClock>,
+    /// the sample rate and buffer size plugins are loaded with.
+    pub config: Arc<RwLock<EngineConfig>>,
+    /// the installed plugins, plugins are loaded from here instead of scanning for them.
+    pub catalog: Arc<PluginCatalog>,
     /// sets where the usb midi input should be routed.
     midi_target: Arc<AtomicUsize>,
     /// midi input type
@@ -56,431 +168,1289 @@ pub struct Mixer {
 
 impl Mixer {
     // #[new]
-    pub fn new() -> (Self, OutputDevice) {
-        let channels: Arc<Vec<_>> = Arc::new(
-            (0..N_CHANNELS).map(|_| Arc::new(RwLock::new(PluginChain::default()))).collect()
-        );
-        let effects:Arc<RwLock<Vec<SinglePlugin>>> = Arc::new(RwLock::new(Vec::new()));
-
-        // start audio output
-        let params = OutputDeviceParameters {
-            channels_count: 2,
-            sample_rate: SAMPLE_RATE,
-            channel_sample_count: BUFFER_FRAMES,
-        };
-
-        // start audio playback
-        let device = run_output_device(params, {
-            let channels = channels.clone();
-            let effects = effects.clone();
-
-            // Cutoff and sampling frequencies
-            let f0 = ((20_000 + 20) / 2).hz();
-            let fs = SAMPLE_RATE.hz();
-            let coeffs =
-                Coefficients::<f32>::from_params(Type::AllPass, fs, f0, Q_BUTTERWORTH_F32).unwrap();
-            let mut allpass = DirectForm1::<f32>::new(coeffs);
-            // let mut allpass = AllPass::new(1.0, SAMPLE_RATE, 0.5);
-            // let chunk_size = BUFFER_FRAMES / current_num_threads();
-            info!("BUFFER_FRAMES = {BUFFER_FRAMES}");
-            // info!("num parallel = {:?}", std::thread::available_parallelism());
-            info!("num threads (default) = {}", current_num_threads());
-            // info!("chunk_size = {chunk_size}");
-            // rayon::ThreadPoolBuilder::new().use_current_thread().num_threads(3).build_global().unwrap();
-            // rayon::ThreadPoolBuilder::new().use_current_thread().build_global().unwrap();
-            // info!("num_threads (customized) = {}", current_num_threads());
-
-            move |data| {
-                // Create audio buffers
-                // let mut pre_master_buss: Vec<Vec<Sample>> =
-                //     (0..BUFFER_FRAMES).map(|_| Vec::with_capacity(N_CHANNELS)).collect();
-
-                // let mut generated_samples = channels
-                //     .par_iter()
-                //     .filter_map(|locked_channel| {
-                //         let chan_samples = locked_channel
-                //             .write()
-                //             .map(|mut unlocked_channel| unlocked_channel.get_samples(BUFFER_FRAMES));
-                //
-                //         match chan_samples {
-                //             Ok(samples) => samples,
-                //             Err(e) => {
-                //                 error!("{e}");
-                //                 None
-                //             }
-                //         }
-                //     });
-                //
-                // generated_samples.for_each(|chan_samples| {
-                //     chan_samples.into_iter().enumerate().for_each(|(i, sample)| pre_master_buss[i].push(sample));
-                // });
-               
-                // let mut m_zip = {
-                let pre_master_bus: Vec<Sample> = {
-                    // debug!("new buffer");
-
-                    let  m_zip = {
-                        let samples_by_channel: Vec<std::vec::IntoIter<Sample>> = channels
-                            // .iter()
-                            // .filter(|locked_channel| {
-                            //     locked_channel
-                            //         .read()
-                            //         .is_ok_and(|unlocked_channel| unlocked_channel.sound_gen.is_some()) 
-                            // }).into_iter()
-                            // .collect::<Vec<_>>()
-                            .par_iter()
-                            .filter_map(|locked_channel| {
-                                let chan_samples = locked_channel
-                                    .write()
-                                    .map(|mut unlocked_channel| unlocked_channel.get_samples(BUFFER_FRAMES));
-
-                                match chan_samples {
-                                    Ok(samples) => samples.map(|samples| samples.into_iter()),
-                                    Err(e) => {
-                                        error!("{e}");
-                                        None
-                                    }
-                                }
-                            }).collect();
-                        Multizip(samples_by_channel)
-                    };
-
-                    // debug!("made buffer");
-                    // let chunked_samples: Vec<_> = std::iter::from_fn(move || {
-                    //     Some(m_zip.by_ref().take(chunk_size).collect()).filter(|chunk: &Vec<_>| !chunk.is_empty())
-                    // }).collect();
-
-                    m_zip.into_iter().map(|samples: Vec<Sample>| { 
-                        let sample: Sample = samples.iter().sum();
-                        // allpass.tick(sample.into()).tanh() as f32
-                        allpass.run(sample).tanh()
-                        // sample.tanh()
-                    }).collect()
-                    
-                    // chunked_samples.par_iter().map(|chunk: &Vec<Vec<Sample>>| { 
-                    //     chunk.into_iter().map(move |samples| {
-                    //         // let sample: Sample =  samples.into_iter().sum();
-                    //         let sample: Sample = samples.into_iter().sum();
-                    //         // allpass.run(sample).tanh()
-                    //         sample.tanh()
-                    //     }).collect::<Vec<_>>()
-                    // }).flatten().collect()
-                };
-
-                // debug!("normalized samples");
-
-                // let chunked_samples: Vec<_> = std::iter::from_fn(move || {
-                //     Some(m_zip.by_ref().take(chunk_size).collect()).filter(|chunk: &Vec<_>| !chunk.is_empty())
-                // }).collect();
-                // let pre_master_bus: Vec<Sample> = chunked_samples.par_iter().map(|chunk| { 
-                // let pre_master_bus: Vec<Sample> = m_zip.map(|samples| { 
-                //     // chunk.into_iter().map(move |samples| {
-                //         // let sample: Sample =  samples.into_iter().sum();
-                //         let sample: Sample = samples.into_iter().sum();
-                //         // allpass.run(sample).tanh()
-                //         sample.tanh()
-                //     // }).collect::<Vec<_>>()
-                // }).collect();
-
-                // debug!("made pre_master_bus");
-
-                // let (rms, peak) = analyze_buffer(&pre_master_bus);
-                // debug!("pre-effects => RMS={:6.4} Peak={:6.4}", rms, peak);
-
-                let post_master_bus = if let Ok(mut effects) = effects.write() {
-                    // let mut input = pre_master_bus.clone();
-                    // let mut input = pre_master_bus;
-
-                    // effects.iter_mut().for_each(|effect| {
-                    //     let mut output = vec![0.0f32; BUFFER_FRAMES];
-                    //
-                    //     if let Err(e) = effect.process(&[&input], &mut [&mut output], BUFFER_FRAMES) {
-                    //         warn!(
-                    //             "effect plugin @ path {} attempted to produce output but failed with error {e}",
-                    //             effect.info().path.display()
-                    //         );
-                    //     } else {
-                    //         input = output;
-                    //     }
-                    // });
-                    //
-                    // input
-
-                    let input = pre_master_bus;
-
-                    let output = effects.iter_mut().fold(input, |input, effect| {
-                        let mut output = vec![0.0f32; BUFFER_FRAMES];
-
-                        if let Err(e) = effect.process(&[&input], &mut [&mut output], BUFFER_FRAMES) {
-                            warn!(
-                                "effect plugin @ path {} attempted to produce output but failed with error {e}",
-                                effect.info().path.display()
-                            );
-                        }
-
-                        output
-                    });
-
-                    output
-                } else {
-                    pre_master_bus
-                };
-
-                // let (rms, peak) = analyze_buffer(&post_master_bus);
-                // debug!("post-effects => RMS={:6.4} Peak={:6.4}", rms, peak);
-
-                for (samples, value) in data
-                    .chunks_mut(params.channels_count)
-                    .zip(post_master_bus)
-                {
-                    for sample in samples {
-                        *sample = value;
-                    }
-                }
-            }
-        })
-        .expect("failed to start audio thread...");
+    /// starts a mixer with n_channels empty channels (at most MAX_CHANNELS). plugins are looked up
+    /// in catalog.
+    pub fn new(
+        config: EngineConfig,
+        n_channels: usize,
+        output: &dyn OutputBackend,
+        catalog: PluginCatalog,
+    ) -> (Self, OutputHandle) {
+        if n_channels > MAX_CHANNELS {
+            warn!("a mixer can have at most {MAX_CHANNELS} channels, not {n_channels}");
+        }
 
+        let n_channels = n_channels.min(MAX_CHANNELS);
+        let (commands, commands_recv) = bounded(QUEUE_LEN);
+        let (garbage, garbage_recv) = bounded::<Garbage>(QUEUE_LEN);
+        let view = Arc::new(RwLock::new(MixerView {
+            channels: vec![ChannelView::default(); n_channels],
+            aux_effects: vec![Vec::new(); N_AUX],
+            ..Default::default()
+        }));
+        // every channel that could be added gets a meter now, so the audio thread never has to
+        // grow them
+        let meters = Arc::new(Meters::new(MAX_CHANNELS));
+        let engine = MixerEngine::new(n_channels, config, meters.clone(), commands_recv, garbage);
+        let clock = engine.clock();
+        let engine = Arc::new(Mutex::new(engine));
         let midi_target = Arc::new(AtomicUsize::new(0));
 
+        spawn(move || janitor_thread(garbage_recv));
+
         let jh = spawn({
-            let channels = channels.clone();
+            let commands = commands.clone();
             let target = midi_target.clone();
+            let clock = clock.clone();
 
             || {
-                midi_thread(channels, target);
+                midi_thread(commands, target, clock);
             }
         });
 
-        (Self { channels, effects, /* _device */ midi_target, _jh: Arc::new(jh) }, device)
+        let mixer = Self {
+            commands,
+            view,
+            meters,
+            engine,
+            clock,
+            config: Arc::new(RwLock::new(config)),
+            catalog: Arc::new(catalog),
+            /* _device */ midi_target,
+            _jh: Arc::new(jh),
+        };
+        let device = output
+            .start(mixer.engine.clone(), config)
+            .expect("failed to start audio thread...");
+
+        (mixer, device)
     }
-}
 
-#[pymethods]
-impl Mixer {
-    /// returns a list of available effects
-    pub fn get_plugin_list(&self) -> Vec<(String, PathBuf)> {
-        // Create scanner and scan for plugins
-        let Ok(scanner) = Scanner::new() else {
-            return Vec::new();
+    /// queues a command for the audio thread without blocking. false if the queue is full, the
+    /// view must only change once this succeeds or it'll disagree with the engine.
+    pub fn send(&self, command: MixerCommand) -> bool {
+        self.commands
+            .try_send(command)
+            .inspect_err(|e| error!("failed to queue a command for the audio thread: {e}"))
+            .is_ok()
+    }
+
+    /// sends midi events to the instrument of a channel. they play a buffer from now, keeping
+    /// the spacing they're sent with.
+    pub fn send_midi(&self, channel_i: usize, events: impl IntoIterator<Item = MidiEvent>) {
+        self.send_midi_at(channel_i, events, self.clock.stamp());
+    }
+
+    /// sends midi events to the instrument of a channel, to play at frame of the engine's clock.
+    /// ones that are late play at the start of the next buffer.
+    pub fn send_midi_at(
+        &self,
+        channel_i: usize,
+        events: impl IntoIterator<Item = MidiEvent>,
+        frame: u64,
+    ) {
+        for event in events {
+            self.send(MixerCommand::SendMidi {
+                channel_i,
+                event,
+                frame,
+            });
+        }
+    }
+
+    /// appends an empty channel and returns its index, or None if there are already MAX_CHANNELS.
+    pub fn add_channel(&mut self) -> Option<usize> {
+        let Ok(mut view) = self.view.write() else {
+            return None;
+        };
+
+        if view.channels.len() >= MAX_CHANNELS {
+            error!("a mixer can have at most {MAX_CHANNELS} channels");
+            return None;
+        }
+
+        if !self.send(MixerCommand::AddChannel(Box::new(PluginChain::new(
+            self.get_config().buffer_frames,
+        )))) {
+            return None;
+        }
+
+        view.channels.push(ChannelView::default());
+
+        Some(view.channels.len() - 1)
+    }
+
+    /// removes channel_i along with its instrument and effects. the channels after it move down
+    /// by one and sidechains from it are disconnected. the last channel can't be removed.
+    pub fn remove_channel(&mut self, channel_i: usize) -> bool {
+        let Ok(mut view) = self.view.write() else {
+            return false;
+        };
+
+        if channel_i >= view.channels.len() || view.channels.len() == 1 {
+            return false;
+        }
+
+        if !self.send(MixerCommand::RemoveChannel(channel_i)) {
+            return false;
+        }
+
+        view.channels.remove(channel_i);
+        view.all_effects_mut()
+            .for_each(|effect| effect.settings.channel_removed(channel_i));
+        // the usb keyboard stays on the channel it was playing, or falls back to the first one
+        let _ = self
+            .midi_target
+            .fetch_update(
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+                |target| match target {
+                    target if target == channel_i => Some(0),
+                    target if target > channel_i => Some(target - 1),
+                    _ => None,
+                },
+            );
+
+        true
+    }
+
+    /// edits the settings of a channel and hands the result to the audio thread.
+    fn update_settings(&mut self, channel_i: usize, update: impl FnOnce(&mut ChannelSettings)) {
+        let Ok(mut view) = self.view.write() else {
+            return;
         };
-        let Ok(plugins) = scanner.scan() else {
-            return Vec::new();
+        let Some(channel) = view.channels.get_mut(channel_i) else {
+            return;
         };
 
-        plugins.into_iter().map(|p| (p.name, p.path)).collect()
+        let mut settings = channel.settings;
+        update(&mut settings);
+
+        if self.send(MixerCommand::SetChannelSettings {
+            channel_i,
+            settings,
+        }) {
+            channel.settings = settings;
+        }
     }
 
-    pub fn play_notes(&mut self, notes: Vec<u8>, channel: usize) {
-        let on_events: Vec<MidiEvent> = notes.into_iter().map(|note| MidiEvent::note_on(note, 100, 0, 0)).collect();
+    /// reads the settings of a channel.
+    fn settings(&self, channel_i: usize) -> Option<ChannelSettings> {
+        self.view
+            .read()
+            .ok()
+            .and_then(|view| view.channels.get(channel_i).map(|channel| channel.settings))
+    }
 
-        if let Ok(mut channel) = self.channels[channel].write() {
-            if let Some(sound_gen) = &mut channel.sound_gen {
-                if let Err(e) = sound_gen.send_midi(&on_events) {
-                    error!("sending midi failed with error {e}");
-                }
-            } else {
-                error!("no sound generator");
-            }
-        } else {
-            error!("failed to write channel {channel}");
+    /// makes sound_gen the instrument of channel, a plugin or a native instrument or (in tests) a
+    /// mock.
+    pub(crate) fn set_processor(&mut self, channel_i: usize, sound_gen: Box<dyn Processor>) {
+        let Ok(mut view) = self.view.write() else {
+            return;
+        };
+        let Some(channel) = view.channels.get_mut(channel_i) else {
+            return;
+        };
+
+        info!("setting the instrument for channel no. {channel_i} to {sound_gen}");
+        let health = Arc::new(PluginHealth::default());
+        let name = sound_gen.name().into();
+        let categories = sound_gen.categories();
+        let params = sound_gen.parameters();
+
+        if self.send(MixerCommand::SetInstrument {
+            channel_i,
+            sound_gen,
+            health: health.clone(),
+        }) {
+            channel.instrument = Some(name);
+            channel.instrument_health = health;
+            channel.categories = categories;
+            channel.instrument_params = params;
         }
     }
 
-    pub fn stop_notes(&mut self, notes: Vec<u8>, channel: usize) {
-        let on_events: Vec<MidiEvent> = notes.into_iter().map(|note| MidiEvent::note_off(note, 100, 0, 0)).collect();
+    /// raises an IndexError if there's no channel channel_i.
+    fn check_channel(&self, channel_i: usize) -> PyResult<()> {
+        let n_channels = self.get_n_channels();
 
-        if let Ok(mut channel) = self.channels[channel].write() {
-            if let Some(sound_gen) = &mut channel.sound_gen {
-                if let Err(e) = sound_gen.send_midi(&on_events) {
-                    error!("sending midi failed with error {e}");
-                }
-            } else {
-                error!("no sound generator");
-            }
+        if channel_i >= n_channels {
+            return Err(missing_chain(EffectChain::Channel(channel_i), n_channels));
+        }
+
+        Ok(())
+    }
+
+    fn has_instrument(&self, channel_i: usize) -> bool {
+        self.view.read().is_ok_and(|view| {
+            view.channels
+                .get(channel_i)
+                .is_some_and(|channel| channel.instrument.is_some())
+        })
+    }
+
+    /// adds the effect made by make_effect, which is only called if the chain has room for it.
+    /// raises an IndexError if the chain's channel or aux bus doesn't exist and a RuntimeError if
+    /// the chain is full, along with the errors of make_effect.
+    fn add_effect_to(
+        &mut self,
+        chain: EffectChain,
+        location: usize,
+        settings: EffectSettings,
+        make_effect: impl FnOnce(EngineConfig) -> Result<Box<dyn Processor>, PluginError>,
+    ) -> PyResult<()> {
+        let Ok(mut view) = self.view.write() else {
+            return Err(PyRuntimeError::new_err("the mixer's view is poisoned"));
+        };
+        let n_channels = view.channels.len();
+        let Some(effects) = view.effects_mut(chain) else {
+            return Err(missing_chain(chain, n_channels));
+        };
+
+        if effects.len() >= N_EFFECTS {
+            return Err(PyRuntimeError::new_err(format!(
+                "the effect chain {chain:?} is full, it holds {N_EFFECTS} effects"
+            )));
+        }
+
+        let plugin = make_effect(self.get_config())?;
+        let health = Arc::new(PluginHealth::default());
+        let effect = EffectView {
+            name: plugin.name().into(),
+            health: health.clone(),
+            settings,
+            params: plugin.parameters(),
+        };
+
+        if !self.send(MixerCommand::AddEffect {
+            chain,
+            location,
+            plugin,
+            health,
+            settings,
+        }) {
+            return Err(PyRuntimeError::new_err(
+                "the audio thread's command queue is full",
+            ));
+        }
+
+        if location < effects.len() {
+            effects.insert(location, effect);
         } else {
-            error!("failed to write channel {channel}");
+            effects.push(effect);
         }
+
+        Ok(())
     }
 
-    /// sets the instrument plugin for channel, to synth. the synth param is a pathbuf gotten from
-    /// Mixer.get_plugin_list
-    pub fn set_instrument(&mut self, channel_i: usize, synth: String) {
-        // let do_set_instrument = |channel: | {
-        //             };
+    fn rm_effect_from(&mut self, chain: EffectChain, effect: usize) {
+        let Ok(mut view) = self.view.write() else {
+            return;
+        };
+        let Some(effects) = view.effects_mut(chain) else {
+            return;
+        };
 
-        let Some(Ok(channel)) = &mut self
-            .channels
-            .get(channel_i)
-            .map(|lock_writer| lock_writer.write())
+        if effect < effects.len() && self.send(MixerCommand::RmEffect { chain, effect }) {
+            effects.remove(effect);
+        }
+    }
+
+    fn move_effect_in(&mut self, chain: EffectChain, effect: usize, location: usize) {
+        let Ok(mut view) = self.view.write() else {
+            return;
+        };
+
+        if let Some(effects) = view.effects_mut(chain)
+            && effect < effects.len()
+            && location < effects.len()
+            && self.send(MixerCommand::MoveEffect {
+                chain,
+                from: effect,
+                to: location,
+            })
+        {
+            move_slot(effects, effect, location);
+        }
+    }
+
+    fn swap_effects_in(&mut self, chain: EffectChain, a: usize, b: usize) {
+        let Ok(mut view) = self.view.write() else {
+            return;
+        };
+
+        if let Some(effects) = view.effects_mut(chain)
+            && a < effects.len()
+            && b < effects.len()
+            && self.send(MixerCommand::SwapEffects { chain, a, b })
+        {
+            effects.swap(a, b);
+        }
+    }
+
+    fn replace_effect_in(
+        &mut self,
+        chain: EffectChain,
+        effect: usize,
+        make_effect: impl FnOnce(EngineConfig) -> Result<Box<dyn Processor>, PluginError>,
+    ) -> Result<(), PluginError> {
+        let Ok(mut view) = self.view.write() else {
+            return Ok(());
+        };
+        let Some(slot) = view
+            .effects_mut(chain)
+            .and_then(|effects| effects.get_mut(effect))
+        else {
+            return Ok(());
+        };
+
+        let plugin = make_effect(self.get_config())?;
+        let health = Arc::new(PluginHealth::default());
+        let name = plugin.name().into();
+        let params = plugin.parameters();
+
+        if self.send(MixerCommand::ReplaceEffect {
+            chain,
+            effect,
+            plugin,
+            health: health.clone(),
+        }) {
+            slot.name = name;
+            slot.health = health;
+            slot.params = params;
+        }
+
+        Ok(())
+    }
+
+    /// edits the bypass/mix of an effect slot and hands the result to the audio thread.
+    fn update_effect(
+        &mut self,
+        chain: EffectChain,
+        effect: usize,
+        update: impl FnOnce(&mut EffectSettings),
+    ) {
+        let Ok(mut view) = self.view.write() else {
+            return;
+        };
+        let Some(slot) = view
+            .effects_mut(chain)
+            .and_then(|effects| effects.get_mut(effect))
         else {
             return;
         };
 
-        if let Some(plugin) = load_plugin(&synth) {
-            info!(
-                "setting the instrument for channel no. {channel_i} to the plugin, {synth}, from path, {}", plugin.info().path.display()
-            );
-            channel.sound_gen = Some(plugin);
+        let mut settings = slot.settings;
+        update(&mut settings);
+
+        if self.send(MixerCommand::SetEffectSettings {
+            chain,
+            effect,
+            settings,
+        }) {
+            slot.settings = settings;
         }
     }
 
-    /// adds an effect to an effect chain if channel is None the effect is on the mixer not a
-    /// channel
-    pub fn add_effect(&mut self, channel: Option<usize>, location: usize, effect: String) {
-        if let Some(channel_i) = channel {
-            let Some(Ok(mut channel)) = self
-                .channels
-                .get(channel_i)
-                .map(|lock_writer| lock_writer.write())
-            else {
-                return;
-            };
+    /// routes source (or nothing) into the sidechain input of an effect.
+    fn set_sidechain(
+        &mut self,
+        chain: EffectChain,
+        effect: usize,
+        source: Option<usize>,
+        pre_fader: bool,
+    ) {
+        let n_channels = self.get_n_channels();
+
+        if let Some(source) = source
+            && (source >= n_channels || chain == EffectChain::Channel(source))
+        {
+            error!("can't sidechain {chain:?} from channel {source}");
+            return;
+        }
 
-            if channel.effects.len() >= N_EFFECTS {
-                return;
-            }
+        let sidechain = source.map(|channel| Sidechain { channel, pre_fader });
+        self.update_effect(chain, effect, |settings| settings.sidechain = sidechain);
+    }
 
-            if let Some(plugin) = load_plugin(&effect) {
-                if location < channel.effects.len() {
-                    channel.effects.insert(location, plugin);
-                } else {
-                    channel.effects.push(plugin);
-                }
-            }
-        } else if let Ok(mut effects) = self.effects.write()
-            && effects.len() < N_EFFECTS
+    fn effect_health(&self, chain: EffectChain, effect: usize) -> Option<Arc<PluginHealth>> {
+        let view = self.view.read().ok()?;
+
+        view.effects(chain)?
+            .get(effect)
+            .map(|slot| slot.health.clone())
+    }
+
+    fn instrument_health(&self, channel_i: usize) -> Option<Arc<PluginHealth>> {
+        let view = self.view.read().ok()?;
+        let channel = view.channels.get(channel_i)?;
+
+        channel
+            .instrument
+            .as_ref()
+            .map(|_| channel.instrument_health.clone())
+    }
+
+    fn effect_settings(&self, chain: EffectChain, effect: usize) -> Option<EffectSettings> {
+        let view = self.view.read().ok()?;
+
+        view.effects(chain)?.get(effect).map(|slot| slot.settings)
+    }
+
+    fn params(&self, target: ParamTarget) -> Vec<Parameter> {
+        self.view
+            .read()
+            .ok()
+            .and_then(|view| view.params(target).cloned())
+            .unwrap_or_default()
+    }
+
+    fn param(&self, target: ParamTarget, index: usize) -> Option<f32> {
+        let view = self.view.read().ok()?;
+
+        view.params(target)?.get(index).map(|param| param.value)
+    }
+
+    /// sets a parameter of an instrument or effect, clamped to its range.
+    fn set_param(&mut self, target: ParamTarget, index: usize, value: f32) {
+        let Ok(mut view) = self.view.write() else {
+            return;
+        };
+        let Some(param) = view
+            .params_mut(target)
+            .and_then(|params| params.get_mut(index))
+        else {
+            error!("{target:?} has no parameter {index}");
+            return;
+        };
+
+        let value = param.clamp(value);
+
+        if self.send(MixerCommand::SetParameter {
+            target,
+            index,
+            value,
+        }) {
+            param.value = value;
+        }
+    }
+
+    /// sends the command built around a reply channel and waits for the audio thread to answer.
+    /// the commands queued before it are applied first, so the answer matches the view.
+    fn request<T>(&self, command: impl FnOnce(Sender<T>) -> MixerCommand) -> Option<T> {
+        let (reply, answer) = bounded(1);
+
+        if !self.send(command(reply)) {
+            return None;
+        }
+
+        answer
+            .recv_timeout(REPLY_TIMEOUT)
+            .inspect_err(|e| error!("the audio thread didn't answer. {e}"))
+            .ok()
+    }
+
+    /// lends the plugins in targets out of the engine, so their state can be read or restored
+    /// here without holding up the audio thread. their slots are silent (or pass their input
+    /// through) until they're returned. None if the engine didn't lend them, then nothing changed.
+    fn lend_plugins(&self, targets: impl IntoIterator<Item = ParamTarget>) -> Option<Vec<Loan>> {
+        let loans = targets.into_iter().map(Loan::new).collect();
+
+        self.request(|reply| MixerCommand::LendPlugins { loans, reply })
+    }
+
+    /// puts plugins from lend_plugins back into their slots.
+    fn return_plugins(&self, loans: Vec<Loan>) {
+        // unlike other commands this one can't be dropped, so it waits for room in the queue
+        if let Err(e) = self
+            .commands
+            .send_timeout(MixerCommand::ReturnPlugins(loans), REPLY_TIMEOUT)
         {
-            if let Some(plugin) = load_plugin(&effect) {
-                if location < effects.len() {
-                    effects.insert(location, plugin);
-                } else {
-                    effects.push(plugin);
+            error!(
+                "failed to return lent plugins to the audio thread, their slots stay empty. {e}"
+            );
+        }
+    }
+
+    /// the states of the plugins in targets, in the same order.
+    fn plugin_states(
+        &self,
+        targets: impl IntoIterator<Item = ParamTarget>,
+    ) -> Vec<Option<PluginState>> {
+        let targets: Vec<ParamTarget> = targets.into_iter().collect();
+        let Some(loans) = self.lend_plugins(targets.iter().copied()) else {
+            return vec![None; targets.len()];
+        };
+        let states = loans
+            .iter()
+            .map(|loan| {
+                loan.lent
+                    .then(|| PluginState::capture(loan.plugin.as_ref()))
+                    .flatten()
+            })
+            .collect();
+
+        self.return_plugins(loans);
+
+        states
+    }
+
+    fn plugin_state(&self, target: ParamTarget) -> Option<PluginState> {
+        self.plugin_states([target]).pop().flatten()
+    }
+
+    /// hands each state to the plugin in its slot. returns false if any of them couldn't be
+    /// restored. if the engine doesn't lend out the plugins in time none of them are touched.
+    fn restore_states<'a>(
+        &mut self,
+        states: impl IntoIterator<Item = (ParamTarget, &'a PluginState)>,
+    ) -> bool {
+        let (targets, states): (Vec<ParamTarget>, Vec<&PluginState>) = states.into_iter().unzip();
+        let Some(mut loans) = self.lend_plugins(targets) else {
+            return false;
+        };
+        let restored: Vec<Option<Vec<Parameter>>> = loans
+            .iter_mut()
+            .zip(states)
+            .map(|(loan, state)| {
+                if !loan.lent {
+                    warn!(
+                        "no plugin at {:?} to restore the state of {} to",
+                        loan.target, state.name
+                    );
+                    return None;
                 }
+
+                state.restore(loan.plugin.as_mut())
+            })
+            .collect();
+        let targets: Vec<ParamTarget> = loans.iter().map(|loan| loan.target).collect();
+
+        self.return_plugins(loans);
+
+        let Ok(mut view) = self.view.write() else {
+            return false;
+        };
+        let mut all_restored = true;
+
+        // a restored state can change every parameter
+        for (target, params) in targets.into_iter().zip(restored) {
+            match (params, view.params_mut(target)) {
+                (Some(params), Some(view_params)) => *view_params = params,
+                (Some(_), None) => {}
+                (None, _) => all_restored = false,
             }
         }
+
+        all_restored
+    }
+}
+
+#[pymethods]
+impl Mixer {
+    /// returns a list of available effects
+    pub fn get_plugin_list(&self) -> Vec<(String, PathBuf)> {
+        self.catalog
+            .plugins()
+            .into_iter()
+            .map(|p| (p.name, p.path))
+            .collect()
+    }
+
+    /// everything the plugin catalogue knows about the installed plugins.
+    pub fn get_plugins(&self) -> Vec<PluginEntry> {
+        self.catalog.plugins()
+    }
+
+    /// scans for installed plugins and remembers them, for get_plugin_list and for loading. slow,
+    /// every plugin is loaded once. the GIL is released meanwhile, so it can run on a python
+    /// thread while the UI goes on. returns how many were found, or None if the scan failed.
+    pub fn rescan_plugins(&self, py: Python<'_>) -> Option<usize> {
+        let catalog = self.catalog.clone();
+
+        py.detach(move || catalog.rescan())
+    }
+
+    pub fn play_notes(&mut self, notes: Vec<u8>, channel: usize) {
+        if !self.has_instrument(channel) {
+            error!("no sound generator");
+            return;
+        }
+
+        self.send_midi(
+            channel,
+            notes
+                .into_iter()
+                .map(|note| MidiEvent::note_on(note, 100, 0, 0)),
+        );
+    }
+
+    pub fn stop_notes(&mut self, notes: Vec<u8>, channel: usize) {
+        if !self.has_instrument(channel) {
+            error!("no sound generator");
+            return;
+        }
+
+        self.send_midi(
+            channel,
+            notes
+                .into_iter()
+                .map(|note| MidiEvent::note_off(note, 100, 0, 0)),
+        );
+    }
+
+    /// sets the instrument plugin for channel, to synth. synth is a plugin's unique ID or path, as
+    /// listed by Mixer.get_plugins, or its name if no other plugin has it. raises a LookupError if
+    /// it's missing or ambiguous, and an IndexError if there's no such channel.
+    pub fn set_instrument(&mut self, channel_i: usize, synth: String) -> PyResult<()> {
+        self.check_channel(channel_i)?;
+        let plugin = load_plugin(&self.catalog, &synth, self.get_config())?;
+        self.set_processor(channel_i, plugin);
+
+        Ok(())
+    }
+
+    /// sets the instrument for channel to one of the built-in instruments, no plugin needed.
+    /// raises an IndexError if there's no such channel.
+    pub fn set_native_instrument(
+        &mut self,
+        channel_i: usize,
+        instrument: NativeInstrument,
+    ) -> PyResult<()> {
+        self.check_channel(channel_i)?;
+        self.set_processor(channel_i, instrument.processor(self.get_config()));
+
+        Ok(())
+    }
+
+    /// adds an effect to a channel's effect chain, or to the master effects when channel is None.
+    /// every effect method reads a channel of None that way. effect is identified like in
+    /// set_instrument. raises an IndexError if there's no such channel and a RuntimeError if the
+    /// chain is full.
+    pub fn add_effect(
+        &mut self,
+        channel: Option<usize>,
+        location: usize,
+        effect: String,
+    ) -> PyResult<()> {
+        let catalog = self.catalog.clone();
+
+        self.add_effect_to(
+            channel.into(),
+            location,
+            EffectSettings::default(),
+            |config| load_plugin(&catalog, &effect, config),
+        )
+    }
+
+    /// adds one of the built-in effects to an effect chain, no plugin needed. raises like
+    /// add_effect.
+    pub fn add_native_effect(
+        &mut self,
+        channel: Option<usize>,
+        location: usize,
+        effect: NativeEffect,
+    ) -> PyResult<()> {
+        self.add_effect_to(channel.into(), location, effect.settings(), |config| {
+            Ok(effect.processor(config))
+        })
     }
 
-    /// removes an effect to an effect chain if channel is None the effect is on the mixer not
-    /// a channel
+    /// removes an effect from an effect chain.
     pub fn rm_effect(&mut self, channel: Option<usize>, effect: usize) {
-        if let Some(channel_i) = channel {
-            if let Some(Ok(mut channel)) = self
-                .channels
-                .get(channel_i)
-                .map(|lock_writer| lock_writer.write())
-            {
-                channel.effects.remove(effect);
-            }
-        } else if let Ok(mut effects) = self.effects.write() {
-            effects.remove(effect);
+        self.rm_effect_from(channel.into(), effect);
+    }
+
+    /// moves an effect to location in the same chain, keeping the plugin and its state.
+    pub fn move_effect(&mut self, channel: Option<usize>, effect: usize, location: usize) {
+        self.move_effect_in(channel.into(), effect, location);
+    }
+
+    /// swaps two effects of the same chain, keeping both plugins and their state.
+    pub fn swap_effects(&mut self, channel: Option<usize>, a: usize, b: usize) {
+        self.swap_effects_in(channel.into(), a, b);
+    }
+
+    /// loads new_effect into the slot of effect, which keeps its bypass and dry/wet. the old
+    /// plugin is dropped.
+    pub fn replace_effect(
+        &mut self,
+        channel: Option<usize>,
+        effect: usize,
+        new_effect: String,
+    ) -> PyResult<()> {
+        let catalog = self.catalog.clone();
+
+        Ok(self.replace_effect_in(channel.into(), effect, |config| {
+            load_plugin(&catalog, &new_effect, config)
+        })?)
+    }
+
+    /// puts one of the built-in effects into the slot of effect, which keeps its bypass and
+    /// dry/wet.
+    pub fn replace_with_native_effect(
+        &mut self,
+        channel: Option<usize>,
+        effect: usize,
+        new_effect: NativeEffect,
+    ) {
+        let _ = self.replace_effect_in(channel.into(), effect, |config| {
+            Ok(new_effect.processor(config))
+        });
+    }
+
+    /// adds an effect to the chain of aux bus aux. raises like add_effect.
+    pub fn add_aux_effect(&mut self, aux: usize, location: usize, effect: String) -> PyResult<()> {
+        let catalog = self.catalog.clone();
+
+        self.add_effect_to(
+            EffectChain::Aux(aux),
+            location,
+            EffectSettings::default(),
+            |config| load_plugin(&catalog, &effect, config),
+        )
+    }
+
+    /// adds one of the built-in effects to the chain of aux bus aux. raises like add_effect.
+    pub fn add_aux_native_effect(
+        &mut self,
+        aux: usize,
+        location: usize,
+        effect: NativeEffect,
+    ) -> PyResult<()> {
+        // an aux bus is the wet path of its sends, so even a delay or reverb starts fully wet there
+        self.add_effect_to(
+            EffectChain::Aux(aux),
+            location,
+            EffectSettings::default(),
+            |config| Ok(effect.processor(config)),
+        )
+    }
+
+    /// removes an effect from the chain of aux bus aux.
+    pub fn rm_aux_effect(&mut self, aux: usize, effect: usize) {
+        self.rm_effect_from(EffectChain::Aux(aux), effect);
+    }
+
+    /// bypasses (or un-bypasses) an effect, crossfading so it doesn't click. the effect keeps its
+    /// state.
+    pub fn set_effect_bypass(&mut self, channel: Option<usize>, effect: usize, bypass: bool) {
+        self.update_effect(channel.into(), effect, |settings| settings.bypass = bypass);
+    }
+
+    /// sets the dry/wet of an effect, 0.0 is fully dry and 1.0 is fully wet.
+    pub fn set_effect_mix(&mut self, channel: Option<usize>, effect: usize, mix: f32) {
+        if !(0.0..=1.0).contains(&mix) {
+            return;
+        }
+
+        self.update_effect(channel.into(), effect, |settings| settings.mix = mix);
+    }
+
+    /// feeds another channel's signal into the sidechain input of an effect, or disconnects it
+    /// when source is None. a channel can't sidechain its own effects.
+    #[pyo3(signature = (channel, effect, source, pre_fader=false))]
+    pub fn set_effect_sidechain(
+        &mut self,
+        channel: Option<usize>,
+        effect: usize,
+        source: Option<usize>,
+        pre_fader: bool,
+    ) {
+        self.set_sidechain(channel.into(), effect, source, pre_fader);
+    }
+
+    /// returns the (source channel, pre_fader) feeding an effect's sidechain.
+    pub fn get_effect_sidechain(
+        &self,
+        channel: Option<usize>,
+        effect: usize,
+    ) -> Option<(usize, bool)> {
+        self.effect_settings(channel.into(), effect)
+            .and_then(|settings| settings.sidechain)
+            .map(|sidechain| (sidechain.channel, sidechain.pre_fader))
+    }
+
+    #[pyo3(signature = (aux, effect, source, pre_fader=false))]
+    pub fn set_aux_effect_sidechain(
+        &mut self,
+        aux: usize,
+        effect: usize,
+        source: Option<usize>,
+        pre_fader: bool,
+    ) {
+        self.set_sidechain(EffectChain::Aux(aux), effect, source, pre_fader);
+    }
+
+    /// returns the (bypass, mix) of an effect.
+    pub fn get_effect_settings(
+        &self,
+        channel: Option<usize>,
+        effect: usize,
+    ) -> Option<(bool, f32)> {
+        self.effect_settings(channel.into(), effect)
+            .map(|settings| (settings.bypass, settings.mix))
+    }
+
+    pub fn set_aux_effect_bypass(&mut self, aux: usize, effect: usize, bypass: bool) {
+        self.update_effect(EffectChain::Aux(aux), effect, |settings| {
+            settings.bypass = bypass
+        });
+    }
+
+    pub fn set_aux_effect_mix(&mut self, aux: usize, effect: usize, mix: f32) {
+        if !(0.0..=1.0).contains(&mix) {
+            return;
+        }
+
+        self.update_effect(EffectChain::Aux(aux), effect, |settings| settings.mix = mix);
+    }
+
+    /// returns the (bypass, mix) of an effect on aux bus aux.
+    pub fn get_aux_effect_settings(&self, aux: usize, effect: usize) -> Option<(bool, f32)> {
+        self.effect_settings(EffectChain::Aux(aux), effect)
+            .map(|settings| (settings.bypass, settings.mix))
+    }
+
+    /// the parameters of a channel's instrument, with their ranges and defaults. the values are
+    /// the last ones set (or restored with a state) through the mixer, changes made in the plugin
+    /// itself, by its own GUI, automation or presets, don't show up here. empty if the channel
+    /// has no instrument.
+    pub fn get_instrument_params(&self, channel_i: usize) -> Vec<Parameter> {
+        self.params(ParamTarget::Instrument(channel_i))
+    }
+
+    /// the value of one of the parameters from get_instrument_params.
+    pub fn get_instrument_param(&self, channel_i: usize, index: usize) -> Option<f32> {
+        self.param(ParamTarget::Instrument(channel_i), index)
+    }
+
+    /// sets a parameter of a channel's instrument by index, clamped to its range.
+    pub fn set_instrument_param(&mut self, channel_i: usize, index: usize, value: f32) {
+        self.set_param(ParamTarget::Instrument(channel_i), index, value);
+    }
+
+    /// the parameters of an effect. like in get_instrument_params, the values are the last ones
+    /// set through the mixer.
+    pub fn get_effect_params(&self, channel: Option<usize>, effect: usize) -> Vec<Parameter> {
+        self.params(ParamTarget::Effect(channel.into(), effect))
+    }
+
+    /// the value of one of the parameters from get_effect_params.
+    pub fn get_effect_param(
+        &self,
+        channel: Option<usize>,
+        effect: usize,
+        index: usize,
+    ) -> Option<f32> {
+        self.param(ParamTarget::Effect(channel.into(), effect), index)
+    }
+
+    /// sets a parameter of an effect by index, clamped to its range.
+    pub fn set_effect_param(
+        &mut self,
+        channel: Option<usize>,
+        effect: usize,
+        index: usize,
+        value: f32,
+    ) {
+        self.set_param(ParamTarget::Effect(channel.into(), effect), index, value);
+    }
+
+    /// the parameters of an effect on aux bus aux, see get_effect_params.
+    pub fn get_aux_effect_params(&self, aux: usize, effect: usize) -> Vec<Parameter> {
+        self.params(ParamTarget::Effect(EffectChain::Aux(aux), effect))
+    }
+
+    /// the value of one of the parameters from get_aux_effect_params.
+    pub fn get_aux_effect_param(&self, aux: usize, effect: usize, index: usize) -> Option<f32> {
+        self.param(ParamTarget::Effect(EffectChain::Aux(aux), effect), index)
+    }
+
+    pub fn set_aux_effect_param(&mut self, aux: usize, effect: usize, index: usize, value: f32) {
+        self.set_param(
+            ParamTarget::Effect(EffectChain::Aux(aux), effect),
+            index,
+            value,
+        );
+    }
+
+    /// the full internal state of a channel's instrument, to design a sound once and get it back
+    /// later. None if the channel has no instrument or it has no state to give.
+    pub fn get_instrument_state(&self, channel_i: usize) -> Option<PluginState> {
+        self.plugin_state(ParamTarget::Instrument(channel_i))
+    }
+
+    /// restores a state from get_instrument_state. the instrument has to be the one it came from.
+    /// returns whether it was restored.
+    pub fn set_instrument_state(&mut self, channel_i: usize, state: PluginState) -> bool {
+        self.restore_states([(ParamTarget::Instrument(channel_i), &state)])
+    }
+
+    /// the full internal state of an effect, see get_instrument_state.
+    pub fn get_effect_state(&self, channel: Option<usize>, effect: usize) -> Option<PluginState> {
+        self.plugin_state(ParamTarget::Effect(channel.into(), effect))
+    }
+
+    /// restores a state from get_effect_state, see set_instrument_state.
+    pub fn set_effect_state(
+        &mut self,
+        channel: Option<usize>,
+        effect: usize,
+        state: PluginState,
+    ) -> bool {
+        self.restore_states([(ParamTarget::Effect(channel.into(), effect), &state)])
+    }
+
+    pub fn get_aux_effect_state(&self, aux: usize, effect: usize) -> Option<PluginState> {
+        self.plugin_state(ParamTarget::Effect(EffectChain::Aux(aux), effect))
+    }
+
+    pub fn set_aux_effect_state(&mut self, aux: usize, effect: usize, state: PluginState) -> bool {
+        self.restore_states([(ParamTarget::Effect(EffectChain::Aux(aux), effect), &state)])
+    }
+
+    /// the states of every instrument and effect on every channel, aux bus and the master, in one
+    /// go. MixerState.to_bytes turns it into something to save. the plugins are taken out of the
+    /// signal path while their states are read, and instruments stop their notes.
+    pub fn get_plugin_states(&self) -> MixerState {
+        let Ok(view) = self.view.read() else {
+            return MixerState::default();
+        };
+        let channels: Vec<usize> = view
+            .channels
+            .iter()
+            .map(|channel| channel.effects.len())
+            .collect();
+        let aux: Vec<usize> = view.aux_effects.iter().map(Vec::len).collect();
+        let master = view.effects.len();
+        drop(view);
+
+        let chain = |chain: EffectChain, n_effects: usize| {
+            (0..n_effects).map(move |effect| ParamTarget::Effect(chain, effect))
+        };
+        let channel_targets = channels
+            .iter()
+            .enumerate()
+            .flat_map(|(channel_i, n)| chain(EffectChain::Channel(channel_i), *n));
+        let aux_targets = aux
+            .iter()
+            .enumerate()
+            .flat_map(|(aux, n)| chain(EffectChain::Aux(aux), *n));
+        let targets = (0..channels.len())
+            .map(ParamTarget::Instrument)
+            .chain(channel_targets)
+            .chain(aux_targets)
+            .chain(chain(EffectChain::Master, master));
+        // the states come back in the order they were asked for
+        let mut states = self.plugin_states(targets).into_iter();
+        let mut take = |n: usize| -> Vec<Option<PluginState>> { states.by_ref().take(n).collect() };
+
+        MixerState {
+            instruments: take(channels.len()),
+            channel_effects: channels.iter().map(|n| take(*n)).collect(),
+            aux_effects: aux.iter().map(|n| take(*n)).collect(),
+            master_effects: take(master),
+        }
+    }
+
+    /// restores what get_plugin_states saved onto the same instruments and effects, slot by slot.
+    /// a state whose slot now holds a different plugin is skipped. returns whether every state was
+    /// restored.
+    pub fn set_plugin_states(&mut self, states: MixerState) -> bool {
+        self.restore_states(states.slots())
+    }
+
+    /// whether a channel's instrument is working, failing or quarantined. None if the channel has
+    /// no instrument.
+    pub fn get_instrument_status(&self, channel_i: usize) -> Option<PluginStatus> {
+        self.instrument_health(channel_i)
+            .map(|health| health.status())
+    }
+
+    /// whether an effect is working, failing or quarantined.
+    pub fn get_effect_status(&self, channel: Option<usize>, effect: usize) -> Option<PluginStatus> {
+        self.effect_health(channel.into(), effect)
+            .map(|health| health.status())
+    }
+
+    pub fn get_aux_effect_status(&self, aux: usize, effect: usize) -> Option<PluginStatus> {
+        self.effect_health(EffectChain::Aux(aux), effect)
+            .map(|health| health.status())
+    }
+
+    /// lets a quarantined instrument back into the signal path.
+    pub fn reset_instrument(&self, channel_i: usize) {
+        if let Some(health) = self.instrument_health(channel_i) {
+            health.reset();
+        }
+    }
+
+    /// lets a quarantined effect back into the signal path.
+    pub fn reset_effect(&self, channel: Option<usize>, effect: usize) {
+        if let Some(health) = self.effect_health(channel.into(), effect) {
+            health.reset();
+        }
+    }
+
+    pub fn reset_aux_effect(&self, aux: usize, effect: usize) {
+        if let Some(health) = self.effect_health(EffectChain::Aux(aux), effect) {
+            health.reset();
+        }
+    }
+
+    /// the names of the effects on aux bus aux, in processing order.
+    pub fn get_aux_effect_names(&self, aux: usize) -> Vec<String> {
+        self.view
+            .read()
+            .ok()
+            .and_then(|view| {
+                view.aux_effects
+                    .get(aux)
+                    .map(|effects| effects.iter().map(|effect| effect.name.clone()).collect())
+            })
+            .unwrap_or_default()
+    }
+
+    /// sets how much of a channel is sent to aux bus aux. pre-fader sends ignore the channel's
+    /// volume and pan.
+    #[pyo3(signature = (channel_i, aux, level, pre_fader=false))]
+    pub fn set_send(&mut self, channel_i: usize, aux: usize, level: f32, pre_fader: bool) {
+        if aux >= N_AUX || !(0.0..=1.25).contains(&level) {
+            return;
         }
+
+        self.update_settings(channel_i, |settings| {
+            settings.sends[aux] = AuxSend { level, pre_fader }
+        });
+    }
+
+    /// returns the (level, pre_fader) of a channel's send to aux bus aux.
+    pub fn get_send(&self, channel_i: usize, aux: usize) -> Option<(f32, bool)> {
+        self.settings(channel_i).and_then(|settings| {
+            settings
+                .sends
+                .get(aux)
+                .map(|send| (send.level, send.pre_fader))
+        })
     }
 
     pub fn get_plugin_names(&self) -> Vec<Option<String>> {
-        self.channels.iter().map(|channel| {
-            let Some(plugin) = &channel.read().unwrap().sound_gen else {
-                return None;
-            };
-            Some(plugin.info().name.clone())
-        }).collect()
+        self.view
+            .read()
+            .unwrap()
+            .channels
+            .iter()
+            .map(|channel| channel.instrument.clone())
+            .collect()
     }
 
     pub fn set_volume(&mut self, channel_i: usize, volume: f32) {
-        if volume > 1.25 || volume < 0.0 {
+        if !(0.0..=1.25).contains(&volume) {
+            return;
+        }
+
+        self.update_settings(channel_i, |settings| settings.volume = volume);
+    }
+
+    /// sets the stereo position of a channel, -1.0 is hard left, 0.0 is center and 1.0 is hard
+    /// right.
+    pub fn set_pan(&mut self, channel_i: usize, pan: f32) {
+        if !(-1.0..=1.0).contains(&pan) {
             return;
         }
 
-        if let Some(Ok(mut channel)) = self
+        self.update_settings(channel_i, |settings| settings.pan = pan);
+    }
+
+    /// mutes or un-mutes a channel. this doesn't touch the channels volume.
+    pub fn set_mute(&mut self, channel_i: usize, mute: bool) {
+        self.update_settings(channel_i, |settings| settings.mute = mute);
+    }
+
+    /// while any channel is soloed, only soloed and solo-safe channels are heard.
+    pub fn set_solo(&mut self, channel_i: usize, solo: bool) {
+        self.update_settings(channel_i, |settings| settings.solo = solo);
+    }
+
+    /// a solo-safe channel isn't silenced when other channels are soloed.
+    pub fn set_solo_safe(&mut self, channel_i: usize, solo_safe: bool) {
+        self.update_settings(channel_i, |settings| settings.solo_safe = solo_safe);
+    }
+
+    pub fn is_muted(&self, channel_i: usize) -> bool {
+        self.settings(channel_i)
+            .is_some_and(|settings| settings.mute)
+    }
+
+    pub fn is_soloed(&self, channel_i: usize) -> bool {
+        self.settings(channel_i)
+            .is_some_and(|settings| settings.solo)
+    }
+
+    pub fn is_solo_safe(&self, channel_i: usize) -> bool {
+        self.settings(channel_i)
+            .is_some_and(|settings| settings.solo_safe)
+    }
+
+    /// how many channels the mixer has right now.
+    pub fn get_n_channels(&self) -> usize {
+        self.view
+            .read()
+            .map(|view| view.channels.len())
+            .unwrap_or(0)
+    }
+
+    /// returns the meter readings of every channel followed by the master bus's. this never
+    /// blocks the audio thread.
+    pub fn get_meters(&self) -> (Vec<MeterReading>, MeterReading) {
+        (
+            self.meters
+                .channels
+                .iter()
+                .take(self.get_n_channels())
+                .map(|meter| meter.reading())
+                .collect(),
+            self.meters.master.reading(),
+        )
+    }
+
+    /// resets the clip indicators of every meter.
+    pub fn clear_clips(&self) {
+        self.meters
             .channels
-            .get(channel_i)
-            .map(|lock_writer| lock_writer.write())
-        {
-            channel.volume = volume;
+            .iter()
+            .for_each(|meter| meter.clear_clip());
+        self.meters.master.clear_clip();
+    }
+
+    /// sets the pan law used by every channel.
+    pub fn set_pan_law(&mut self, pan_law: PanLaw) {
+        if let Ok(mut view) = self.view.write() {
+            if self.send(MixerCommand::SetPanLaw(pan_law)) {
+                view.pan_law = pan_law;
+            }
+        }
+    }
+
+    pub fn get_pan_law(&self) -> PanLaw {
+        self.view
+            .read()
+            .map(|view| view.pan_law)
+            .unwrap_or_default()
+    }
+
+    /// picks the master bus saturation mode, oversampling and limiter ceiling.
+    pub fn set_master_settings(&mut self, settings: MasterSettings) {
+        if let Ok(mut view) = self.view.write() {
+            if self.send(MixerCommand::SetMasterSettings(settings)) {
+                view.master = settings;
+            }
         }
     }
 
+    pub fn get_master_settings(&self) -> MasterSettings {
+        self.view.read().map(|view| view.master).unwrap_or_default()
+    }
+
+    /// how far the master saturation/limiter turned the last buffer down, in dB.
+    pub fn get_gain_reduction(&self) -> f32 {
+        self.meters.gain_reduction.load()
+    }
+
+    /// the sample rate and buffer size the engine is running at.
+    pub fn get_config(&self) -> EngineConfig {
+        self.config.read().map(|config| *config).unwrap_or_default()
+    }
+
     pub fn set_usb_midi_target(&mut self, channel_i: usize) {
         self.midi_target.store(channel_i, Ordering::Relaxed);
     }
 
+    /// the channel the usb midi keyboards play.
+    pub fn get_usb_midi_target(&self) -> usize {
+        self.midi_target.load(Ordering::Relaxed)
+    }
+
     pub fn is_drums(&self, channel_i: usize) -> bool {
-        if let Some(Ok(Some(categories))) = &self
-            .channels
-            .get(channel_i)
-            .map(|lock_writer| lock_writer.read().map(|channel| channel.sound_gen.as_ref().map(|p| p.get_categories())))
-        {
-            categories.contains(&"Drum".into())
-        } else {
-            false
-        }
+        self.view.read().is_ok_and(|view| {
+            view.channels
+                .get(channel_i)
+                .is_some_and(|channel| channel.categories.contains(&"Drum".into()))
+        })
     }
 }
 
-pub fn load_plugin(plugin_name: &str) -> Option<SinglePlugin> {
-    // Create scanner and scan for plugins
-    let scanner = Scanner::new().ok()?;
-    let plugins = scanner.scan().ok()?;
-    let synth_info = plugins.iter().find(|p| p.name == plugin_name)?;
-    let plugin = scanner.load(&synth_info).map(|mut plugin| 
-        {
-            if let Err(e) = plugin.initialize(SAMPLE_RATE as f64, BUFFER_FRAMES) {
-                warn!("plugin failed to init. {e}");
-            } else {
-                info!("loaded and inited plugin: {plugin_name}");
-            }
+/// the IndexError for a chain whose channel or aux bus doesn't exist.
+fn missing_chain(chain: EffectChain, n_channels: usize) -> PyErr {
+    PyIndexError::new_err(match chain {
+        EffectChain::Channel(channel_i) => {
+            format!("channel {channel_i} is out of range, the mixer has {n_channels}")
+        }
+        EffectChain::Aux(aux) => format!("aux bus {aux} is out of range, the mixer has {N_AUX}"),
+        EffectChain::Master => "the mixer always has master effects".into(),
+    })
+}
 
-            plugin
-        });  // .map(|plugin| plugin.initialize(SAMPLE_RATE as f64, BUFFER_FRAMES));
+/// loads and initializes the plugin with the unique ID, path or (unambiguous) name plugin.
+pub fn load_plugin(
+    catalog: &PluginCatalog,
+    plugin: &str,
+    config: EngineConfig,
+) -> Result<Box<dyn Processor>, PluginError> {
+    let entry = catalog.find(plugin).inspect_err(|e| warn!("{e}"))?;
+    let loaded = Scanner::new().and_then(|scanner| scanner.load(&entry.info()));
+
+    match loaded {
+        Ok(plugin) => {
+            let mut plugin = Vst3::new(plugin);
+            plugin.initialize(config);
+
+            Ok(Box::new(plugin))
+        }
+        Err(e) => {
+            let e = PluginError::Load(entry.path, e.to_string());
+            warn!("{e}");
 
-    if plugin.is_err() {
-        warn!(
-            "failed to find the plugin with name {plugin_name}."
-        )
+            Err(e)
+        }
     }
-
-    plugin.ok()
 }
 
-fn midi_thread(channels: Arc<Vec<Arc<RwLock<PluginChain>>>>, target: Arc<AtomicUsize>) {
+fn midi_thread(commands: Sender<MixerCommand>, target: Arc<AtomicUsize>, clock: Arc<EngineClock>) {
     let midi_in = &mut MidiInput::new("Dream-of-DAW").expect("failed to build MIDI input");
     midi_in.ignore(Ignore::None);
     let mut midi_devs = HashMap::new();
 
     let send_midi = move |midi: MidiEvent| {
-        let channel = &channels[target.load(Ordering::Relaxed)];
-
-        if let Ok(mut channel) = channel.write() {
-            if let Some(sound_gen) = &mut channel.sound_gen {
-                if let Err(e) = sound_gen.send_midi(&[midi]) {
-                    error!("sending midi failed with error {e}");
-                }
-            } else {
-                error!("no sound generator");
-            }
-        } else {
-            error!("failed to write channel {}", target.load(Ordering::Relaxed));
+        let channel_i = target.load(Ordering::Relaxed);
+        let frame = clock.stamp();
+
+        if let Err(e) = commands.try_send(MixerCommand::SendMidi {
+            channel_i,
+            event: midi,
+            frame,
+        }) {
+            error!("failed to send midi to channel {channel_i}: {e}");
         }
     };
-    let into_u8 = |channel| {
-        match channel {
-            Channel::Ch1 => 0,
-            Channel::Ch2 => 1,
-            Channel::Ch3 => 2,
-            Channel::Ch4 => 3,
-            Channel::Ch5 => 4,
-            Channel::Ch6 => 5,
-            Channel::Ch7 => 6,
-            Channel::Ch8 => 7,
-            Channel::Ch9 => 8,
-            Channel::Ch10 => 9,
-            Channel::Ch11 => 10,
-            Channel::Ch12 => 11,
-            Channel::Ch13 => 12,
-            Channel::Ch14 => 13,
-            Channel::Ch15 => 14,
-            Channel::Ch16 => 15,
-        }
+    let into_u8 = |channel| match channel {
+        Channel::Ch1 => 0,
+        Channel::Ch2 => 1,
+        Channel::Ch3 => 2,
+        Channel::Ch4 => 3,
+        Channel::Ch5 => 4,
+        Channel::Ch6 => 5,
+        Channel::Ch7 => 6,
+        Channel::Ch8 => 7,
+        Channel::Ch9 => 8,
+        Channel::Ch10 => 9,
+        Channel::Ch11 => 10,
+        Channel::Ch12 => 11,
+        Channel::Ch13 => 12,
+        Channel::Ch14 => 13,
+        Channel::Ch15 => 14,
+        Channel::Ch16 => 15,
     };
 
     loop {
@@ -490,7 +1460,8 @@ fn midi_thread(channels: Arc<Vec<Arc<RwLock<PluginChain>>>>, target: Arc<AtomicU
             if let Ok(in_port_name) = midi_in.port_name(&in_port) {
                 let send_midi = send_midi.clone();
 
-                let midi_in = MidiInput::new(format!("Dream-of-DAW-{i}").as_str()).expect("failed to build MIDI input");
+                let midi_in = MidiInput::new(format!("Dream-of-DAW-{i}").as_str())
+                    .expect("failed to build MIDI input");
 
                 if let Ok(conn_in) = midi_in.connect(
                     &in_port,
@@ -500,9 +1471,30 @@ fn midi_thread(channels: Arc<Vec<Arc<RwLock<PluginChain>>>>, target: Arc<AtomicU
                         let msg = MidiMsg::from_midi(message);
 
                         let msg = match msg {
-                            Ok((MidiMsg::ChannelVoice { channel, msg: ChannelVoiceMsg::NoteOn { note, velocity } }, _)) => MidiEvent::note_on(note, velocity, into_u8(channel), 0),
-                            Ok((MidiMsg::ChannelVoice { channel, msg: ChannelVoiceMsg::NoteOff { note, velocity } }, _)) => MidiEvent::note_off(note, velocity, into_u8(channel), 0),
-                            Ok((MidiMsg::ChannelVoice { channel, msg: ChannelVoiceMsg::ControlChange { control: ControlChange::CC { control, value } } }, _)) => MidiEvent::control_change(control, value, into_u8(channel), 0),
+                            Ok((
+                                MidiMsg::ChannelVoice {
+                                    channel,
+                                    msg: ChannelVoiceMsg::NoteOn { note, velocity },
+                                },
+                                _,
+                            )) => MidiEvent::note_on(note, velocity, into_u8(channel), 0),
+                            Ok((
+                                MidiMsg::ChannelVoice {
+                                    channel,
+                                    msg: ChannelVoiceMsg::NoteOff { note, velocity },
+                                },
+                                _,
+                            )) => MidiEvent::note_off(note, velocity, into_u8(channel), 0),
+                            Ok((
+                                MidiMsg::ChannelVoice {
+                                    channel,
+                                    msg:
+                                        ChannelVoiceMsg::ControlChange {
+                                            control: ControlChange::CC { control, value },
+                                        },
+                                },
+                                _,
+                            )) => MidiEvent::control_change(control, value, into_u8(channel), 0),
                             _ => return,
                         };
 
@@ -519,25 +1511,6 @@ fn midi_thread(channels: Arc<Vec<Arc<RwLock<PluginChain>>>>, target: Arc<AtomicU
     }
 }
 
-#[allow(dead_code)]
-/// Calculate RMS and peak levels for left and right channels (planar format)
-fn analyze_buffer(audio: &[f32]) -> (f32, f32) {
-    let frames = audio.len();
-
-    let mut sum_right = 0.0f32;
-    let mut peak_right = 0.0f32;
-
-    for i in 0..frames {
-        sum_right += audio[i] * audio[i];
-
-        peak_right = peak_right.max(audio[i].abs());
-    }
-
-    let rms_right = (sum_right / frames as f32).sqrt();
-
-    (rms_right, peak_right)
-}
-
 // #[cfg(test)]
 // mod test {
 //     use std::{thread::sleep, time::Duration};
@@ -579,7 +1552,7 @@ fn analyze_buffer(audio: &[f32]) -> (f32, f32) {
 //
 //         sleep(Duration::from_secs(5));
 //
-//         if let Some(plugin) = &mixer.channels[chan].read().unwrap().sound_gen { 
+//         if let Some(plugin) = &mixer.channels[chan].read().unwrap().sound_gen {
 //             log::debug!("sound_gen = {:?}", plugin.info().name.clone());
 //         }
 //
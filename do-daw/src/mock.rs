@@ -0,0 +1,128 @@
+//! a stand-in plugin, so tests can drive the engine without any VST3s installed.
+
+use crate::{
+    Sample,
+    config::EngineConfig,
+    instruments::ALL_NOTES_OFF,
+    midi_queue::MidiQueue,
+    parameter::Parameter,
+    state::{params_from_bytes, params_to_bytes},
+    traits::{ParamInfo, Processor},
+};
+use rack::{MidiEventKind, prelude::*};
+use std::{
+    fmt::{self, Display},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// its one parameter, level.
+const LEVEL: ParamInfo = ParamInfo {
+    name: "Level",
+    min: 0.0,
+    max: 1.0,
+    default: 1.0,
+    unit: "",
+};
+
+/// as an instrument it puts out level on both sides while any note is held, starting and stopping
+/// on the frame the midi falls on. as an effect it scales its input by level.
+pub struct MockPlugin {
+    level: f32,
+    held: usize,
+    midi: MidiQueue,
+    /// while set, every buffer fails
+    pub failing: Arc<AtomicBool>,
+}
+
+impl MockPlugin {
+    pub fn new(level: f32) -> Self {
+        Self {
+            level,
+            held: 0,
+            midi: MidiQueue::default(),
+            failing: Arc::default(),
+        }
+    }
+}
+
+impl Processor for MockPlugin {
+    fn name(&self) -> &str {
+        "Mock"
+    }
+
+    fn initialize(&mut self, _config: EngineConfig) {}
+
+    fn process(
+        &mut self,
+        inputs: &[&[Sample]],
+        outputs: &mut [&mut [Sample]],
+        num_frames: usize,
+    ) -> bool {
+        if self.failing.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let mut midi = self.midi.pop_before(u64::MAX).peekable();
+
+        for frame in 0..num_frames {
+            while let Some((_, event)) = midi.next_if(|(offset, _)| *offset <= frame as u64) {
+                match event.kind {
+                    MidiEventKind::NoteOn { velocity: 0, .. } | MidiEventKind::NoteOff { .. } => {
+                        self.held = self.held.saturating_sub(1)
+                    }
+                    MidiEventKind::NoteOn { .. } => self.held += 1,
+                    MidiEventKind::ControlChange {
+                        controller: ALL_NOTES_OFF,
+                        ..
+                    } => self.held = 0,
+                    _ => {}
+                }
+            }
+
+            for (i, output) in outputs.iter_mut().enumerate() {
+                output[frame] = match inputs.get(i) {
+                    Some(input) => input[frame] * self.level,
+                    None if self.held > 0 => self.level,
+                    None => 0.0,
+                };
+            }
+        }
+
+        true
+    }
+
+    fn send_midi(&mut self, event: MidiEvent) {
+        let _ = self.midi.push(event.sample_offset as u64, event);
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![Parameter::native(0, &LEVEL, self.level)]
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        if index != 0 {
+            return false;
+        }
+
+        self.level = value.clamp(LEVEL.min, LEVEL.max);
+
+        true
+    }
+
+    fn get_state(&self) -> Option<Vec<u8>> {
+        Some(params_to_bytes([self.level]))
+    }
+
+    fn set_state(&mut self, state: &[u8]) -> bool {
+        params_from_bytes(state, 1).is_some_and(|values| self.set_parameter(0, values[0]))
+    }
+}
+
+impl Display for MockPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mock plugin")
+    }
+}
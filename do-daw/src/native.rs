@@ -0,0 +1,178 @@
+//! the native instruments and effects as a plugin chain sees them.
+
+use crate::{
+    Sample,
+    config::EngineConfig,
+    midi_queue::MidiQueue,
+    parameter::Parameter,
+    state::{params_from_bytes, params_to_bytes},
+    traits::{GenSamples, ParamInfo, ProcessSamples, Processor},
+};
+use rack::prelude::MidiEvent;
+use std::fmt::{self, Display};
+
+/// what a native instrument or effect runs on.
+enum Dsp {
+    Instrument {
+        synth: Box<dyn GenSamples>,
+        /// midi for the next process call, by sample offset
+        midi: MidiQueue,
+    },
+    Effect(Box<dyn ProcessSamples>),
+}
+
+/// a native instrument or effect, wrapped up to sit in a plugin chain like a VST3 does.
+pub struct Native {
+    name: &'static str,
+    categories: Vec<String>,
+    dsp: Dsp,
+}
+
+impl Native {
+    pub fn instrument(
+        name: &'static str,
+        categories: Vec<String>,
+        synth: Box<dyn GenSamples>,
+    ) -> Self {
+        Self {
+            name,
+            categories,
+            dsp: Dsp::Instrument {
+                synth,
+                midi: MidiQueue::default(),
+            },
+        }
+    }
+
+    pub fn effect(name: &'static str, processor: Box<dyn ProcessSamples>) -> Self {
+        Self {
+            name,
+            categories: Vec::new(),
+            dsp: Dsp::Effect(processor),
+        }
+    }
+
+    fn params(&self) -> &'static [ParamInfo] {
+        match &self.dsp {
+            Dsp::Instrument { synth, .. } => synth.params(),
+            Dsp::Effect(processor) => processor.params(),
+        }
+    }
+
+    fn get_parameter(&self, index: usize) -> Option<f32> {
+        match &self.dsp {
+            Dsp::Instrument { synth, .. } => synth.get_parameter(index),
+            Dsp::Effect(processor) => processor.get_parameter(index),
+        }
+    }
+}
+
+impl Processor for Native {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn categories(&self) -> Vec<String> {
+        self.categories.clone()
+    }
+
+    fn initialize(&mut self, config: EngineConfig) {
+        match &mut self.dsp {
+            Dsp::Instrument { synth, .. } => synth.set_sample_rate(config.sample_rate),
+            Dsp::Effect(processor) => processor.set_sample_rate(config.sample_rate),
+        }
+    }
+
+    fn process(
+        &mut self,
+        inputs: &[&[Sample]],
+        outputs: &mut [&mut [Sample]],
+        num_frames: usize,
+    ) -> bool {
+        match &mut self.dsp {
+            Dsp::Instrument { synth, midi } => {
+                let [left, right, ..] = outputs else {
+                    return false;
+                };
+
+                // renders up to each event, so it plays on the frame it was sent for
+                let mut start = 0;
+
+                for (offset, event) in midi.pop_before(u64::MAX) {
+                    let offset = (offset as usize).clamp(start, num_frames);
+                    synth.fill_mono_buffer(&mut left[start..offset], offset - start);
+                    synth.handle_midi(&event);
+                    start = offset;
+                }
+
+                // the native instruments are mono
+                synth.fill_mono_buffer(&mut left[start..num_frames], num_frames - start);
+                right[..num_frames].copy_from_slice(&left[..num_frames]);
+            }
+            Dsp::Effect(processor) => {
+                // the effects index both sides of their inputs and outputs
+                if inputs.len() < 2 || outputs.len() < 2 {
+                    return false;
+                }
+
+                processor.process(inputs, outputs, num_frames);
+            }
+        }
+
+        true
+    }
+
+    fn send_midi(&mut self, event: MidiEvent) {
+        if let Dsp::Instrument { synth, midi } = &mut self.dsp
+            && let Err(event) = midi.push(event.sample_offset as u64, event)
+        {
+            synth.handle_midi(&event);
+        }
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        self.params()
+            .iter()
+            .enumerate()
+            .map(|(i, info)| {
+                Parameter::native(i, info, self.get_parameter(i).unwrap_or(info.default))
+            })
+            .collect()
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        match &mut self.dsp {
+            Dsp::Instrument { synth, .. } => synth.set_parameter(index, value),
+            Dsp::Effect(processor) => processor.set_parameter(index, value),
+        }
+    }
+
+    /// the values of its parameters.
+    fn get_state(&self) -> Option<Vec<u8>> {
+        let n_params = self.params().len();
+
+        Some(params_to_bytes(
+            (0..n_params).filter_map(|i| self.get_parameter(i)),
+        ))
+    }
+
+    fn set_state(&mut self, state: &[u8]) -> bool {
+        let Some(values) = params_from_bytes(state, self.params().len()) else {
+            return false;
+        };
+
+        values
+            .into_iter()
+            .enumerate()
+            .all(|(i, value)| self.set_parameter(i, value))
+    }
+}
+
+impl Display for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.dsp {
+            Dsp::Instrument { .. } => write!(f, "native instrument {}", self.name),
+            Dsp::Effect(_) => write!(f, "native effect {}", self.name),
+        }
+    }
+}
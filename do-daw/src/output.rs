@@ -0,0 +1,334 @@
+use crate::{Sample, config::EngineConfig, engine::MixerEngine};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::*;
+use pyo3::prelude::*;
+use rayon::current_num_threads;
+use std::{
+    error::Error,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread::{JoinHandle, sleep, spawn},
+    time::{Duration, Instant},
+};
+use tinyaudio::{OutputDevice, OutputDeviceParameters, run_output_device};
+
+/// which output backend `run` should start the engine with.
+#[pyclass(eq, eq_int, from_py_object)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputKind {
+    /// the sound card, through tinyaudio.
+    #[default]
+    Device,
+    /// pulls buffers on a timer and throws them away.
+    Null,
+    /// pulls buffers on a timer and writes them to a wav file.
+    Wav,
+}
+
+/// somewhere the engine's audio goes. start pulls buffers out of engine until the returned handle
+/// is dropped.
+pub trait OutputBackend {
+    fn start(
+        &self,
+        engine: Arc<Mutex<MixerEngine>>,
+        config: EngineConfig,
+    ) -> Result<OutputHandle, Box<dyn Error>>;
+}
+
+/// builds the backend for kind. wav_path is only used (and is required) by OutputKind::Wav, None
+/// if it's missing.
+pub fn output_backend(
+    kind: OutputKind,
+    wav_path: Option<PathBuf>,
+) -> Option<Box<dyn OutputBackend>> {
+    match (kind, wav_path) {
+        (OutputKind::Device, _) => Some(Box::new(DeviceOutput)),
+        (OutputKind::Null, _) => Some(Box::new(NullOutput::default())),
+        (OutputKind::Wav, Some(path)) => Some(Box::new(WavOutput::new(path, None))),
+        (OutputKind::Wav, None) => None,
+    }
+}
+
+/// a running output. audio stops when this is dropped.
+pub enum OutputHandle {
+    Device(OutputDevice),
+    Thread(SinkThread),
+}
+
+impl OutputHandle {
+    /// blocks until a sink that was given a number of buffers has pulled all of them. returns
+    /// right away for outputs that run until dropped.
+    pub fn wait(self) {
+        if let Self::Thread(mut sink) = self
+            && sink.buffers.is_some()
+            && let Some(jh) = sink.jh.take()
+        {
+            let _ = jh.join();
+        }
+    }
+}
+
+/// the thread behind a timer driven sink. dropping it stops the thread and waits for it.
+pub struct SinkThread {
+    running: Arc<AtomicBool>,
+    buffers: Option<usize>,
+    jh: Option<JoinHandle<()>>,
+}
+
+impl Drop for SinkThread {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        if let Some(jh) = self.jh.take() {
+            let _ = jh.join();
+        }
+    }
+}
+
+impl SinkThread {
+    /// spawns a thread that calls sink with a buffer from engine every EngineConfig.buffer_secs,
+    /// stopping after `buffers` buffers if that is set. like the sound card, it gets silence while
+    /// someone else holds the engine.
+    fn spawn(
+        engine: Arc<Mutex<MixerEngine>>,
+        config: EngineConfig,
+        buffers: Option<usize>,
+        mut sink: impl FnMut(&[Sample], &[Sample]) + Send + 'static,
+    ) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+
+        let jh = spawn({
+            let running = running.clone();
+
+            move || {
+                let period = Duration::from_secs_f32(config.buffer_secs());
+                let silence = vec![0.0; config.buffer_frames];
+                let mut deadline = Instant::now();
+                let mut pulled = 0;
+
+                while running.load(Ordering::Relaxed) && buffers.is_none_or(|n| pulled < n) {
+                    match engine.try_lock() {
+                        Ok(mut engine) => {
+                            let [left, right] = engine.process(config.buffer_frames);
+                            sink(left, right);
+                        }
+                        Err(_) => sink(&silence, &silence),
+                    }
+
+                    pulled += 1;
+                    deadline += period;
+
+                    match deadline.checked_duration_since(Instant::now()) {
+                        Some(wait) => sleep(wait),
+                        // fell behind, like an underrun on a real device. don't try to catch up
+                        None => deadline = Instant::now(),
+                    }
+                }
+            }
+        });
+
+        Self {
+            running,
+            buffers,
+            jh: Some(jh),
+        }
+    }
+}
+
+/// the sound card, through tinyaudio.
+pub struct DeviceOutput;
+
+impl OutputBackend for DeviceOutput {
+    fn start(
+        &self,
+        engine: Arc<Mutex<MixerEngine>>,
+        config: EngineConfig,
+    ) -> Result<OutputHandle, Box<dyn Error>> {
+        let params = OutputDeviceParameters {
+            channels_count: 2,
+            sample_rate: config.sample_rate,
+            channel_sample_count: config.buffer_frames,
+        };
+
+        info!("starting audio output with {config:?}");
+        info!("num threads (default) = {}", current_num_threads());
+
+        // start audio playback
+        let device = run_output_device(params, move |data| {
+            // an offline render (or a reconfigure) owns the engine for now, so the device just
+            // plays silence
+            let Ok(mut engine) = engine.try_lock() else {
+                data.fill(0.0);
+                return;
+            };
+
+            let [left, right] = engine.process(config.buffer_frames);
+
+            for (samples, (left, right)) in data
+                .chunks_mut(params.channels_count)
+                .zip(left.iter().zip(right))
+            {
+                samples[0] = *left;
+                samples[1] = *right;
+            }
+        })?;
+
+        Ok(OutputHandle::Device(device))
+    }
+}
+
+/// runs the engine in real time without a sound card, for CI and for devices whose audio is busy.
+#[derive(Clone, Debug, Default)]
+pub struct NullOutput {
+    /// stop after this many buffers. None runs until the handle is dropped.
+    pub buffers: Option<usize>,
+}
+
+impl OutputBackend for NullOutput {
+    fn start(
+        &self,
+        engine: Arc<Mutex<MixerEngine>>,
+        config: EngineConfig,
+    ) -> Result<OutputHandle, Box<dyn Error>> {
+        info!("starting null audio output with {config:?}");
+
+        Ok(OutputHandle::Thread(SinkThread::spawn(
+            engine,
+            config,
+            self.buffers,
+            |_, _| {},
+        )))
+    }
+}
+
+/// records everything the engine plays, in real time, to a 32-bit float stereo wav file. the file
+/// is finalized when the handle is dropped (or, with buffers set, once they have been written).
+#[derive(Debug)]
+pub struct WavOutput {
+    pub path: PathBuf,
+    /// stop after this many buffers. None runs until the handle is dropped.
+    pub buffers: Option<usize>,
+    /// how many times it's been started
+    starts: AtomicUsize,
+}
+
+impl WavOutput {
+    pub fn new(path: PathBuf, buffers: Option<usize>) -> Self {
+        Self {
+            path,
+            buffers,
+            starts: AtomicUsize::new(0),
+        }
+    }
+
+    /// where the recording of the nth start goes. a restart can't carry on in the same file, the
+    /// sample rate may have changed, so it goes to a numbered file next to it (take-1.wav after
+    /// take.wav) instead of overwriting what was recorded.
+    fn path_for(&self, start: usize) -> PathBuf {
+        if start == 0 {
+            return self.path.clone();
+        }
+
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = self
+            .path
+            .extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy()))
+            .unwrap_or_default();
+
+        self.path
+            .with_file_name(format!("{stem}-{start}{extension}"))
+    }
+}
+
+impl OutputBackend for WavOutput {
+    fn start(
+        &self,
+        engine: Arc<Mutex<MixerEngine>>,
+        config: EngineConfig,
+    ) -> Result<OutputHandle, Box<dyn Error>> {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: config.sample_rate as u32,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let path = self.path_for(self.starts.fetch_add(1, Ordering::Relaxed));
+        let mut writer = Some(WavWriter::create(&path, spec)?);
+
+        info!(
+            "starting wav audio output to {} with {config:?}",
+            path.display()
+        );
+
+        Ok(OutputHandle::Thread(SinkThread::spawn(
+            engine,
+            config,
+            self.buffers,
+            move |left, right| {
+                let Some(wav) = writer.as_mut() else {
+                    return;
+                };
+
+                let written = left.iter().zip(right).try_for_each(|(left, right)| {
+                    wav.write_sample(*left)?;
+                    wav.write_sample(*right)
+                });
+
+                // the header is written when the writer is dropped, the closure is dropped along
+                // with the thread
+                if let Err(e) = written {
+                    error!(
+                        "writing to {} failed: {e}, stopping the wav output",
+                        path.display()
+                    );
+                    writer = None;
+                }
+            },
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OutputBackend, WavOutput};
+    use crate::{config::EngineConfig, engine::MixerEngine, meter::Meters};
+    use crossbeam::channel::bounded;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn wav_output_captures_buffers() {
+        let config = EngineConfig::new(48000, 64);
+        let (_commands, commands_recv) = bounded(1);
+        let (garbage, _garbage_recv) = bounded(1);
+        let engine = MixerEngine::new(0, config, Arc::new(Meters::new(0)), commands_recv, garbage);
+        let path = std::env::temp_dir().join("do_daw_wav_output_test.wav");
+        let output = WavOutput::new(path.clone(), Some(4));
+        let engine = Arc::new(Mutex::new(engine));
+
+        output.start(engine.clone(), config).unwrap().wait();
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 48000);
+        // an engine without channels only ever plays silence
+        let samples: Vec<f32> = reader.into_samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples.len(), 4 * 64 * 2);
+        assert!(samples.iter().all(|s| *s == 0.0));
+
+        // a restart, like a reconfigure does, goes to a new file and leaves the first one be
+        output.start(engine, config).unwrap().wait();
+        let restarted = std::env::temp_dir().join("do_daw_wav_output_test-1.wav");
+        assert_eq!(hound::WavReader::open(&path).unwrap().len(), 4 * 64 * 2);
+        assert_eq!(
+            hound::WavReader::open(&restarted).unwrap().len(),
+            4 * 64 * 2
+        );
+
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(restarted);
+    }
+}
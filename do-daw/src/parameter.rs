@@ -0,0 +1,108 @@
+use crate::traits::ParamInfo;
+use pyo3::prelude::*;
+
+/// a parameter of an instrument or effect and its value, as handed to python.
+#[pyclass(get_all, from_py_object)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Parameter {
+    /// what the parameter is set by, with Mixer.set_instrument_param and friends
+    pub index: usize,
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub unit: String,
+    pub value: f32,
+    /// the value runs from 0.0 to 1.0 across min to max, the way VST3 plugins hand it over
+    pub normalized: bool,
+}
+
+impl Parameter {
+    /// a parameter of a native instrument or effect.
+    pub fn native(index: usize, info: &ParamInfo, value: f32) -> Self {
+        Self {
+            index,
+            name: info.name.into(),
+            min: info.min,
+            max: info.max,
+            default: info.default,
+            unit: info.unit.into(),
+            value,
+            normalized: false,
+        }
+    }
+
+    /// value, clamped to the parameter's range. that's 0.0 to 1.0 for a normalized one.
+    pub fn clamp(&self, value: f32) -> f32 {
+        if self.normalized {
+            value.clamp(0.0, 1.0)
+        } else {
+            value.clamp(self.min, self.max.max(self.min))
+        }
+    }
+
+    /// the value in the parameter's own units, between min and max.
+    fn plain(&self) -> f32 {
+        if self.normalized {
+            self.min + self.value * (self.max - self.min)
+        } else {
+            self.value
+        }
+    }
+}
+
+#[pymethods]
+impl Parameter {
+    /// the value the way a screen would show it, e.g. "1200 Hz" or "0.35".
+    #[getter]
+    pub fn display(&self) -> String {
+        // fewer decimals the wider the range, so every value takes about the same room
+        let range = self.max - self.min;
+        let decimals = if range >= 100.0 {
+            0
+        } else if range >= 10.0 {
+            1
+        } else {
+            2
+        };
+        let value = format!("{:.*}", decimals, self.plain());
+
+        if self.unit.is_empty() {
+            value
+        } else {
+            format!("{value} {}", self.unit)
+        }
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "Parameter(index={}, name={:?}, value={})",
+            self.index,
+            self.name,
+            self.display()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Parameter;
+
+    #[test]
+    fn normalized_params_clamp_to_one_and_show_their_range() {
+        let cutoff = Parameter {
+            name: "Cutoff".into(),
+            min: 20.0,
+            max: 20_000.0,
+            default: 1.0,
+            unit: "Hz".into(),
+            value: 0.5,
+            normalized: true,
+            ..Parameter::default()
+        };
+
+        assert_eq!(cutoff.clamp(2.0), 1.0);
+        assert_eq!(cutoff.clamp(-1.0), 0.0);
+        assert_eq!(cutoff.display(), "10010 Hz");
+    }
+}
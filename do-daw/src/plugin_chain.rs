@@ -1,75 +1,610 @@
-use crate::{N_EFFECTS, Sample, SinglePlugin};
+use crate::{
+    N_AUX, N_EFFECTS, Sample, StereoBuffer,
+    config::EngineConfig,
+    instruments::ALL_NOTES_OFF,
+    midi_queue::MidiQueue,
+    quarantine::{PluginHealth, PluginStatus, sanitize},
+    traits::Processor,
+};
 use log::*;
 use pyo3::prelude::*;
-use rack::PluginInstance;
+use rack::prelude::MidiEvent;
+use std::{f32::consts::FRAC_PI_4, fmt::Display, sync::Arc};
 
-#[pyclass]
-pub struct PluginChain {
-    pub sound_gen: Option<SinglePlugin>,
-    pub effects: Vec<SinglePlugin>,
+/// how a channels pan position is turned into left and right gains.
+#[pyclass(eq, eq_int, from_py_object)]
+#[derive(Default, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum PanLaw {
+    /// 0 dB at center, the far side is faded out linearly (a balance control).
+    Linear,
+    /// -3 dB at center, the summed power stays the same across the whole pan range.
+    #[default]
+    ConstantPower,
+    /// -6 dB at center, a linear cross-fade between the left and right sides.
+    MinusSixDb,
+}
+
+impl PanLaw {
+    /// returns the (left, right) gains for pan, where -1.0 is hard left and 1.0 is hard right.
+    pub fn gains(&self, pan: f32) -> (f32, f32) {
+        let pan = pan.clamp(-1.0, 1.0);
+
+        match self {
+            Self::Linear => ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0)),
+            Self::ConstantPower => {
+                let angle = (pan + 1.0) * FRAC_PI_4;
+
+                (angle.cos(), angle.sin())
+            }
+            Self::MinusSixDb => ((1.0 - pan) / 2.0, (1.0 + pan) / 2.0),
+        }
+    }
+}
+
+/// how much of a channel goes to one aux bus.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AuxSend {
+    pub level: f32,
+    /// pre-fader sends tap the channel after its effects but before volume and pan, so the aux
+    /// level doesn't follow the channel fader.
+    pub pre_fader: bool,
+}
+
+/// the mixing controls of a channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelSettings {
     pub volume: f32,
+    /// stereo position of the channel, -1.0 is hard left and 1.0 is hard right.
+    pub pan: f32,
+    /// a muted channel is still processed (so effect tails and LFOs stay in phase) but isn't
+    /// mixed into the master bus.
+    pub mute: bool,
+    pub solo: bool,
+    /// a solo-safe channel stays audible while other channels are soloed.
+    pub solo_safe: bool,
+    /// one send per aux bus
+    pub sends: [AuxSend; N_AUX],
 }
 
-impl Default for PluginChain {
+impl Default for ChannelSettings {
     fn default() -> Self {
         Self {
-            sound_gen: None,
-            effects: Vec::with_capacity(N_EFFECTS),
             volume: 1.0,
+            pan: 0.0,
+            mute: false,
+            solo: false,
+            solo_safe: false,
+            sends: Default::default(),
+        }
+    }
+}
+
+impl ChannelSettings {
+    /// whether this channel should be mixed into the master bus. any_solo is true when at least
+    /// one channel of the mixer is soloed.
+    pub fn is_audible(&self, any_solo: bool) -> bool {
+        !self.mute && (!any_solo || self.solo || self.solo_safe)
+    }
+
+    /// whether get_samples needs to keep a copy of the signal before the fader.
+    fn has_pre_fader_send(&self) -> bool {
+        self.sends
+            .iter()
+            .any(|send| send.pre_fader && send.level > 0.0)
+    }
+}
+
+/// allocates a silent stereo buffer that can hold frames samples per side.
+pub fn new_stereo_buffer(frames: usize) -> StereoBuffer {
+    [vec![0.0; frames], vec![0.0; frames]]
+}
+
+/// where an effect's sidechain input comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Sidechain {
+    pub channel: usize,
+    /// tap the source before its volume and pan instead of after.
+    pub pre_fader: bool,
+}
+
+/// the per-slot controls of an effect.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EffectSettings {
+    /// a bypassed effect keeps its state, it just isn't heard (or processed, once the crossfade
+    /// out of it is done).
+    pub bypass: bool,
+    /// dry/wet balance, 0.0 is fully dry and 1.0 is fully wet.
+    pub mix: f32,
+    /// fed to the plugin as a second stereo input (its inputs 3 and 4).
+    pub sidechain: Option<Sidechain>,
+}
+
+impl Default for EffectSettings {
+    fn default() -> Self {
+        Self {
+            bypass: false,
+            mix: 1.0,
+            sidechain: None,
         }
     }
 }
 
+impl EffectSettings {
+    /// how wet the slot should end up.
+    fn target_wet(&self) -> f32 {
+        if self.bypass { 0.0 } else { self.mix }
+    }
+
+    /// keeps the sidechain pointing at the same channel after channel_i is removed from the
+    /// mixer. a sidechain from channel_i itself is disconnected.
+    pub fn channel_removed(&mut self, channel_i: usize) {
+        self.sidechain = self
+            .sidechain
+            .and_then(|sidechain| match sidechain.channel {
+                channel if channel == channel_i => None,
+                channel if channel > channel_i => Some(Sidechain {
+                    channel: channel - 1,
+                    ..sidechain
+                }),
+                _ => Some(sidechain),
+            });
+    }
+}
+
+/// an effect in a chain, along with its bypass and dry/wet.
+pub struct EffectSlot {
+    pub plugin: Box<dyn Processor>,
+    /// shared with the UI, so it can show the plugin as faulted
+    pub health: Arc<PluginHealth>,
+    pub settings: EffectSettings,
+    /// the wet amount the last buffer ended on. bypass and mix changes ramp from here over one
+    /// buffer so they don't click.
+    wet: f32,
+}
+
+impl EffectSlot {
+    pub fn new(
+        plugin: Box<dyn Processor>,
+        health: Arc<PluginHealth>,
+        settings: EffectSettings,
+    ) -> Self {
+        Self {
+            plugin,
+            health,
+            settings,
+            wet: settings.target_wet(),
+        }
+    }
+}
+
+/// moves the slot at from to to, shifting the slots in between over by one. returns false (and
+/// leaves slots alone) if either index is out of range. never allocates.
+pub fn move_slot<T>(slots: &mut [T], from: usize, to: usize) -> bool {
+    if from >= slots.len() || to >= slots.len() {
+        return false;
+    }
+
+    if from < to {
+        slots[from..=to].rotate_left(1);
+    } else {
+        slots[to..=from].rotate_right(1);
+    }
+
+    true
+}
+
+/// blends the dry input into the wet output in place, ramping linearly from start_wet to end_wet
+/// over the buffer.
+pub fn crossfade(dry: [&[Sample]; 2], wet: [&mut [Sample]; 2], start_wet: f32, end_wet: f32) {
+    if start_wet == 1.0 && end_wet == 1.0 {
+        return;
+    }
+
+    for (dry, wet) in dry.into_iter().zip(wet) {
+        let step = (end_wet - start_wet) / wet.len().max(1) as f32;
+
+        for (i, (dry, wet)) in dry.iter().zip(wet.iter_mut()).enumerate() {
+            let amount = start_wet + step * (i + 1) as f32;
+            *wet = dry + (*wet - dry) * amount;
+        }
+    }
+}
+
+/// records how a sound source's buffer went and logs when that changes its status.
+fn check_health(health: &PluginHealth, source: impl Display, ok: bool) {
+    match health.record(ok) {
+        Some(PluginStatus::Failing) => {
+            warn!("{source} failed or put out NaN/Inf, skipping it")
+        }
+        Some(PluginStatus::Quarantined) => {
+            error!("{source} keeps failing, it's quarantined until it's reset")
+        }
+        Some(PluginStatus::Ok) => info!("{source} recovered"),
+        None => {}
+    }
+}
+
+/// stops every note an instrument is playing, on every midi channel.
+pub fn all_notes_off(sound_gen: &mut dyn Processor) {
+    for channel in 0..16 {
+        sound_gen.send_midi(MidiEvent::control_change(ALL_NOTES_OFF, 0, channel, 0));
+    }
+}
+
+/// runs the stereo signal in buffers[0] through effects, ping-ponging between the two buffers so
+/// nothing gets allocated. an effect that fails (or is quarantined) is skipped, so the dry signal
+/// goes on to the next one. sidechain hands out the input of sidechained effects. returns the
+/// index of the buffer that holds the output.
+pub fn run_effects<'a>(
+    effects: &mut [EffectSlot],
+    buffers: &mut [StereoBuffer; 2],
+    buffer_size: usize,
+    sidechain: impl Fn(Sidechain) -> [&'a [Sample]; 2],
+) -> usize {
+    let mut output_i = 0;
+
+    for slot in effects.iter_mut() {
+        let target_wet = slot.settings.target_wet();
+
+        // fully bypassed, the signal just stays where it is
+        if (slot.wet == 0.0 && target_wet == 0.0) || slot.health.is_quarantined() {
+            continue;
+        }
+
+        let [front, back] = &mut *buffers;
+        let (input, output) = if output_i == 0 {
+            (&*front, back)
+        } else {
+            (&*back, front)
+        };
+        let [in_left, in_right] = input;
+        let [left, right] = output;
+        let (in_left, in_right) = (&in_left[..buffer_size], &in_right[..buffer_size]);
+        let (left, right) = (&mut left[..buffer_size], &mut right[..buffer_size]);
+
+        left.fill(0.0);
+        right.fill(0.0);
+
+        let processed = match slot.settings.sidechain {
+            Some(source) => {
+                let [sc_left, sc_right] = sidechain(source);
+
+                slot.plugin.process(
+                    &[
+                        in_left,
+                        in_right,
+                        &sc_left[..buffer_size],
+                        &sc_right[..buffer_size],
+                    ],
+                    &mut [&mut *left, &mut *right],
+                    buffer_size,
+                )
+            }
+            None => slot.plugin.process(
+                &[in_left, in_right],
+                &mut [&mut *left, &mut *right],
+                buffer_size,
+            ),
+        };
+        // both sides are always checked so both get their denormals flushed
+        let ok = sanitize(left) & sanitize(right) && processed;
+        check_health(&slot.health, &slot.plugin, ok);
+
+        if !ok {
+            continue;
+        }
+
+        crossfade([in_left, in_right], [left, right], slot.wet, target_wet);
+        slot.wet = target_wet;
+        output_i = 1 - output_i;
+    }
+
+    output_i
+}
+
+#[pyclass]
+pub struct PluginChain {
+    pub sound_gen: Option<Box<dyn Processor>>,
+    /// health of sound_gen, shared with the UI
+    pub sound_gen_health: Arc<PluginHealth>,
+    /// midi for sound_gen, waiting for the buffer it falls in
+    midi: MidiQueue,
+    pub effects: Vec<EffectSlot>,
+    pub settings: ChannelSettings,
+    /// scratch buffers the chain ping-pongs between, allocated once up front so processing never
+    /// allocates.
+    buffers: [StereoBuffer; 2],
+    /// the output before volume and pan, only filled in while a pre-fader send is up or another
+    /// chain's effect sidechains it pre-fader.
+    pre_fader: StereoBuffer,
+    /// set by the engine while some effect sidechains this channel pre-fader
+    pub pre_fader_tapped: bool,
+    /// which of buffers holds the output of the last get_samples call, None if it made no sound.
+    output_i: Option<usize>,
+}
+
 // impl crate::traits::GenSamples for PluginChain {
 impl PluginChain {
-    pub fn get_samples(&mut self, buffer_size: usize) -> Option<Vec<Sample>> {
-        let sound_gen = self.sound_gen.as_mut()?;
+    /// makes an empty chain that can process up to buffer_frames frames at a time.
+    pub fn new(buffer_frames: usize) -> Self {
+        Self {
+            sound_gen: None,
+            sound_gen_health: Arc::default(),
+            midi: MidiQueue::default(),
+            effects: Vec::with_capacity(N_EFFECTS),
+            settings: ChannelSettings::default(),
+            buffers: [
+                new_stereo_buffer(buffer_frames),
+                new_stereo_buffer(buffer_frames),
+            ],
+            pre_fader: new_stereo_buffer(buffer_frames),
+            pre_fader_tapped: false,
+            output_i: None,
+        }
+    }
+
+    /// how many frames the scratch buffers hold, the most one call to get_samples can render.
+    pub fn buffer_frames(&self) -> usize {
+        self.pre_fader[0].len()
+    }
+
+    /// re-initializes every plugin in the chain and resizes the scratch buffers for config.
+    pub fn reconfigure(&mut self, config: EngineConfig) {
+        if let Some(sound_gen) = self.sound_gen.as_mut() {
+            sound_gen.initialize(config);
+        }
+
+        self.effects
+            .iter_mut()
+            .for_each(|slot| slot.plugin.initialize(config));
+        self.buffers = [
+            new_stereo_buffer(config.buffer_frames),
+            new_stereo_buffer(config.buffer_frames),
+        ];
+        self.pre_fader = new_stereo_buffer(config.buffer_frames);
+        self.output_i = None;
+    }
+
+    /// queues event for the instrument, to play at the engine's frame. when the queue is full the
+    /// event is sent straight away instead, late is better than a stuck note.
+    pub fn queue_midi(&mut self, frame: u64, event: MidiEvent) {
+        if let Err(event) = self.midi.push(frame, event)
+            && let Some(sound_gen) = self.sound_gen.as_mut()
+        {
+            sound_gen.send_midi(event);
+        }
+    }
+
+    /// drops the queued midi and stops every note the instrument is playing, on every midi
+    /// channel.
+    pub fn stop_all_notes(&mut self) {
+        self.midi.clear();
+
+        if let Some(sound_gen) = self.sound_gen.as_mut() {
+            all_notes_off(sound_gen.as_mut());
+        }
+    }
+
+    /// renders buffer_size (at most EngineConfig.buffer_frames) frames of the instrument through the effects,
+    /// volume and pan. buffer_start is the engine's frame the buffer starts at, the queued midi
+    /// that falls in the buffer is sent along with where in it it falls. the result is kept until
+    /// the next call and can be read again with PluginChain::output. sidechain hands out the
+    /// input of sidechained effects.
+    pub fn get_samples<'a>(
+        &mut self,
+        buffer_size: usize,
+        buffer_start: u64,
+        pan_law: PanLaw,
+        sidechain: impl Fn(Sidechain) -> [&'a [Sample]; 2],
+    ) -> Option<[&[Sample]; 2]> {
+        self.output_i = None;
+        let mut sound_gen = self
+            .sound_gen
+            .as_mut()
+            .filter(|_| !self.sound_gen_health.is_quarantined());
+
+        // taken out of the queue even with nothing to play them, so it doesn't fill up
+        for (frame, mut event) in self.midi.pop_before(buffer_start + buffer_size as u64) {
+            if let Some(sound_gen) = sound_gen.as_mut() {
+                // anything late plays at the start of the buffer
+                event.sample_offset = frame.saturating_sub(buffer_start) as u32;
+                sound_gen.send_midi(event);
+            }
+        }
+
+        let sound_gen = sound_gen?;
         // trace!(
         //     "sound generator is located @ {}",
         //     sound_gen.info().path.display()
         // );
 
-        let mut output = vec![0.0f32; buffer_size];
-        if let Err(e) = sound_gen.process(&[], &mut [&mut output], buffer_size) {
-            warn!(
-                "plugin @ path {} attempted to produce output but failed with error {e}",
-                sound_gen.info().path.display()
-            );
+        let [left, right] = &mut self.buffers[0];
+        left[..buffer_size].fill(0.0);
+        right[..buffer_size].fill(0.0);
+
+        let (left, right) = (&mut left[..buffer_size], &mut right[..buffer_size]);
+        let processed = sound_gen.process(&[], &mut [left, right], buffer_size);
+        let ok = sanitize(left) & sanitize(right) && processed;
+        check_health(&self.sound_gen_health, &*sound_gen, ok);
+
+        // a failed buffer goes out as silence, the effects still run so their tails ring out
+        if !ok {
+            left.fill(0.0);
+            right.fill(0.0);
         }
 
-        let mut output = self.effects.iter_mut().fold(output, |input, effect| {
-            let mut output = vec![0.0f32; buffer_size];
+        let output_i = run_effects(&mut self.effects, &mut self.buffers, buffer_size, sidechain);
 
-            if let Err(e) = effect.process(&[&input], &mut [&mut output], buffer_size) {
-                warn!(
-                    "effect plugin @ path {} attempted to produce output but failed with error {e}",
-                    effect.info().path.display()
-                );
-            }
+        if self.settings.has_pre_fader_send() || self.pre_fader_tapped {
+            let [left, right] = &self.buffers[output_i];
+            let [pre_left, pre_right] = &mut self.pre_fader;
+            pre_left[..buffer_size].copy_from_slice(&left[..buffer_size]);
+            pre_right[..buffer_size].copy_from_slice(&right[..buffer_size]);
+        }
+
+        // attenuate output by the channel volume and place it in the stereo field according to its pan
+        let ChannelSettings { volume, pan, .. } = self.settings;
+        let (left_gain, right_gain) = pan_law.gains(pan);
+        let [left, right] = &mut self.buffers[output_i];
+
+        left[..buffer_size]
+            .iter_mut()
+            .for_each(|sample| *sample *= volume * left_gain);
+        right[..buffer_size]
+            .iter_mut()
+            .for_each(|sample| *sample *= volume * right_gain);
+
+        self.output_i = Some(output_i);
+
+        self.output(buffer_size)
+    }
+
+    /// the output of the last get_samples call, None if the chain has no instrument.
+    pub fn output(&self, buffer_size: usize) -> Option<[&[Sample]; 2]> {
+        let [left, right] = &self.buffers[self.output_i?];
 
-            output
-        });
+        Some([&left[..buffer_size], &right[..buffer_size]])
+    }
 
-        // attenuate output by self.volume
-        output = output.iter().map(|sample| sample * self.volume).collect();
+    /// what the last get_samples call fed to aux bus aux, before scaling by the send level. None
+    /// if the chain made no sound.
+    pub fn send_output(&self, aux: usize, buffer_size: usize) -> Option<[&[Sample]; 2]> {
+        let send = self.settings.sends.get(aux)?;
+
+        self.tap(send.pre_fader, buffer_size)
+    }
 
-        Some(output)
+    /// the output of the last get_samples call, from before or after the fader. the pre-fader
+    /// signal is only there while a send or sidechain needs it.
+    pub fn tap(&self, pre_fader: bool, buffer_size: usize) -> Option<[&[Sample]; 2]> {
+        if pre_fader {
+            self.output_i?;
+            let [left, right] = &self.pre_fader;
 
-        // let mut input = output.clone();
+            Some([&left[..buffer_size], &right[..buffer_size]])
+        } else {
+            self.output(buffer_size)
+        }
+    }
+
+    /// whether any of the effects takes a sidechain input.
+    pub fn has_sidechain(&self) -> bool {
+        self.effects
+            .iter()
+            .any(|slot| slot.settings.sidechain.is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ChannelSettings, EffectSettings, PanLaw, Sidechain, crossfade, move_slot};
+
+    fn assert_close(got: (f32, f32), want: (f32, f32)) {
+        assert!(
+            (got.0 - want.0).abs() < 1e-6,
+            "left gain: got {}, want {}",
+            got.0,
+            want.0
+        );
+        assert!(
+            (got.1 - want.1).abs() < 1e-6,
+            "right gain: got {}, want {}",
+            got.1,
+            want.1
+        );
+    }
+
+    #[test]
+    fn pan_laws() {
+        let center = std::f32::consts::FRAC_1_SQRT_2;
+
+        assert_close(PanLaw::Linear.gains(0.0), (1.0, 1.0));
+        assert_close(PanLaw::Linear.gains(0.5), (0.5, 1.0));
+        assert_close(PanLaw::ConstantPower.gains(0.0), (center, center));
+        assert_close(PanLaw::ConstantPower.gains(-1.0), (1.0, 0.0));
+        assert_close(PanLaw::MinusSixDb.gains(0.0), (0.5, 0.5));
+        assert_close(PanLaw::MinusSixDb.gains(1.0), (0.0, 1.0));
+        // out of range positions are clamped to hard left/right
+        assert_close(PanLaw::MinusSixDb.gains(3.0), (0.0, 1.0));
+    }
+
+    #[test]
+    fn solo_semantics() {
+        let plain = ChannelSettings::default();
+        let muted = ChannelSettings {
+            mute: true,
+            ..Default::default()
+        };
+        let soloed = ChannelSettings {
+            solo: true,
+            ..Default::default()
+        };
+        let solo_safe = ChannelSettings {
+            solo_safe: true,
+            ..Default::default()
+        };
+        let muted_solo = ChannelSettings {
+            mute: true,
+            solo: true,
+            ..Default::default()
+        };
+
+        assert!(plain.is_audible(false));
+        assert!(!plain.is_audible(true));
+        assert!(!muted.is_audible(false));
+        assert!(soloed.is_audible(true));
+        assert!(solo_safe.is_audible(true));
+        // mute wins over solo
+        assert!(!muted_solo.is_audible(true));
+    }
+
+    #[test]
+    fn moving_slots() {
+        let mut slots = [0, 1, 2];
+
+        assert!(move_slot(&mut slots, 0, 2));
+        assert_eq!(slots, [1, 2, 0]);
+        assert!(move_slot(&mut slots, 2, 0));
+        assert_eq!(slots, [0, 1, 2]);
+        assert!(move_slot(&mut slots, 1, 1));
+        assert_eq!(slots, [0, 1, 2]);
+        assert!(!move_slot(&mut slots, 3, 0));
+    }
+
+    #[test]
+    fn dry_wet_crossfade() {
+        let dry = [1.0; 4];
+        let mut left = [0.0; 4];
+        let mut right = [0.0; 4];
+
+        // half wet
+        crossfade([&dry, &dry], [&mut left, &mut right], 0.5, 0.5);
+        assert_eq!(left, [0.5; 4]);
+
+        // bypassing ramps from fully wet to fully dry over the buffer instead of jumping
+        let mut left = [0.0; 4];
+        let mut right = [0.0; 4];
+        crossfade([&dry, &dry], [&mut left, &mut right], 1.0, 0.0);
+        assert_eq!(left, [0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(right, left);
+    }
 
-        // self.effects.iter_mut().for_each(|effect| {
-        //     let mut output = vec![0.0f32; buffer_size];
-        //
-        //     if let Err(e) = effect.process(&[&input], &mut [&mut output], buffer_size) {
-        //         warn!(
-        //             "effect plugin @ path {} attempted to produce output but failed with error {e}",
-        //             effect.info().path.display()
-        //         );
-        //     } else {
-        //         input = output.clone();
-        //     }
-        // });
+    #[test]
+    fn sidechains_follow_removed_channels() {
+        let sidechained = |channel| EffectSettings {
+            sidechain: Some(Sidechain {
+                channel,
+                pre_fader: true,
+            }),
+            ..Default::default()
+        };
 
-        // Some(input)
+        let mut settings = sidechained(3);
+        settings.channel_removed(1);
+        assert_eq!(settings, sidechained(2));
+        settings.channel_removed(4);
+        assert_eq!(settings, sidechained(2));
+        settings.channel_removed(2);
+        assert_eq!(settings.sidechain, None);
     }
 }
@@ -0,0 +1,121 @@
+use crate::Sample;
+use pyo3::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// how many buffers in a row a plugin can fail (or put out NaN/Inf) before it's quarantined.
+pub const MAX_CONSECUTIVE_ERRORS: u32 = 8;
+
+/// how a plugin has been behaving, as shown by the UI.
+#[pyclass(eq, eq_int, from_py_object)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PluginStatus {
+    #[default]
+    Ok,
+    /// the last buffer failed, the plugin is skipped for that buffer only.
+    Failing,
+    /// failed too many buffers in a row and is bypassed until it's reset.
+    Quarantined,
+}
+
+/// error counters of one plugin instance. the audio thread records every buffer, the UI reads
+/// (and resets) it without taking a lock.
+#[derive(Debug, Default)]
+pub struct PluginHealth {
+    consecutive: AtomicU32,
+    total: AtomicU32,
+    quarantined: AtomicBool,
+}
+
+impl PluginHealth {
+    /// whether the plugin should be skipped.
+    pub fn is_quarantined(&self) -> bool {
+        self.quarantined.load(Ordering::Relaxed)
+    }
+
+    /// records how a buffer went. returns the new status when it changed, so the caller only
+    /// logs once per fault instead of once per buffer.
+    pub fn record(&self, ok: bool) -> Option<PluginStatus> {
+        if ok {
+            return (self.consecutive.swap(0, Ordering::Relaxed) > 0).then_some(PluginStatus::Ok);
+        }
+
+        self.total.fetch_add(1, Ordering::Relaxed);
+        let consecutive = self.consecutive.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if consecutive >= MAX_CONSECUTIVE_ERRORS {
+            (!self.quarantined.swap(true, Ordering::Relaxed)).then_some(PluginStatus::Quarantined)
+        } else {
+            (consecutive == 1).then_some(PluginStatus::Failing)
+        }
+    }
+
+    pub fn status(&self) -> PluginStatus {
+        if self.is_quarantined() {
+            PluginStatus::Quarantined
+        } else if self.consecutive.load(Ordering::Relaxed) > 0 {
+            PluginStatus::Failing
+        } else {
+            PluginStatus::Ok
+        }
+    }
+
+    /// every failed buffer since the plugin was loaded.
+    pub fn total_errors(&self) -> u32 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// lets a quarantined plugin back into the signal path.
+    pub fn reset(&self) {
+        self.consecutive.store(0, Ordering::Relaxed);
+        self.quarantined.store(false, Ordering::Relaxed);
+    }
+}
+
+/// checks a plugin's output. denormals are flushed to zero (they're only slow, not wrong).
+/// returns false if there was a NaN or Inf, in which case the output shouldn't be used.
+pub fn sanitize(samples: &mut [Sample]) -> bool {
+    let mut finite = true;
+
+    for sample in samples.iter_mut() {
+        if !sample.is_finite() {
+            finite = false;
+        } else if sample.is_subnormal() {
+            *sample = 0.0;
+        }
+    }
+
+    finite
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MAX_CONSECUTIVE_ERRORS, PluginHealth, PluginStatus, sanitize};
+
+    #[test]
+    fn quarantine_after_repeated_errors() {
+        let health = PluginHealth::default();
+
+        assert_eq!(health.record(false), Some(PluginStatus::Failing));
+        assert_eq!(health.record(true), Some(PluginStatus::Ok));
+        assert_eq!(health.record(true), None);
+
+        for _ in 1..MAX_CONSECUTIVE_ERRORS {
+            health.record(false);
+        }
+
+        assert!(!health.is_quarantined());
+        assert_eq!(health.record(false), Some(PluginStatus::Quarantined));
+        assert_eq!(health.record(false), None);
+        assert_eq!(health.status(), PluginStatus::Quarantined);
+        assert_eq!(health.total_errors(), MAX_CONSECUTIVE_ERRORS + 2);
+
+        health.reset();
+        assert_eq!(health.status(), PluginStatus::Ok);
+
+        let mut samples = [0.5, f32::MIN_POSITIVE / 2.0];
+        assert!(sanitize(&mut samples));
+        assert_eq!(samples, [0.5, 0.0]);
+        assert!(!sanitize(&mut [0.0, f32::NAN]));
+        assert!(!sanitize(&mut [f32::INFINITY]));
+    }
+}
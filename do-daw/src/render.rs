@@ -0,0 +1,206 @@
+use crate::{
+    engine::MixerEngine,
+    mixer::Mixer,
+    step_sequencer::{N_STEPS, PPQ, StepSequence, play_step, samples_per_pulse, stop_notes},
+};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::*;
+use std::{fs::File, io::BufWriter, path::Path, sync::RwLock};
+
+/// what an offline render should play.
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    /// the sections to play, in order.
+    pub sections: Vec<usize>,
+    /// how many times the list of sections is played through.
+    pub loops: usize,
+    pub bpm: usize,
+    /// seconds of audio to keep rendering after the last note is stopped, so reverb and delay
+    /// tails aren't cut off.
+    pub tail_secs: f32,
+}
+
+/// renders the step sequence through the mixer as fast as the plugins allow and writes the result
+/// to a 32-bit float stereo wav file at path. the live output device goes silent while this runs,
+/// and midi sent live (even the sequencer's last note offs) is dropped. returns the number of
+/// frames written.
+pub fn render_to_wav(
+    mixer: &Mixer,
+    steps: &[RwLock<Vec<StepSequence>>],
+    options: &RenderOptions,
+    path: &Path,
+) -> hound::Result<usize> {
+    // keeps the live output from pulling buffers out of the engine while we render
+    let Ok(mut engine) = mixer.engine.lock() else {
+        return Err(hound::Error::IoError(std::io::Error::other(
+            "the mixer engine is poisoned",
+        )));
+    };
+    // midi queued live and notes held from before would play into the render, and so would midi
+    // that's sent while it runs. the render hands its own midi straight to the engine
+    engine.stop_all_notes();
+    engine.set_live_midi(false);
+    let rendered = render(&mut engine, steps, options, path);
+    engine.set_live_midi(true);
+
+    rendered
+}
+
+fn render(
+    engine: &mut MixerEngine,
+    steps: &[RwLock<Vec<StepSequence>>],
+    options: &RenderOptions,
+    path: &Path,
+) -> hound::Result<usize> {
+    let config = engine.config();
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: config.sample_rate as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(path, spec)?;
+    let mut note_offs: Vec<(u8, usize)> = Vec::new();
+    let mut written = 0;
+    // the engine's clock when the render starts, the midi is stamped from here so it plays on
+    // exactly the frame it's meant to
+    let start = engine.frame();
+
+    let render_frames = |engine: &mut MixerEngine,
+                         n_frames: usize,
+                         writer: &mut WavWriter<BufWriter<File>>|
+     -> hound::Result<()> {
+        let mut remaining = n_frames;
+
+        while remaining > 0 {
+            let block = remaining.min(config.buffer_frames);
+            let [left, right] = engine.process(block);
+
+            for (left, right) in left.iter().zip(right) {
+                writer.write_sample(*left)?;
+                writer.write_sample(*right)?;
+            }
+
+            remaining -= block;
+        }
+
+        Ok(())
+    };
+
+    let sixteenth_pulse = PPQ / 4;
+    let samples_per_pulse = samples_per_pulse(config.sample_rate, options.bpm);
+    // kept as a float so rounding errors don't add up over long renders
+    let mut clock = 0.0f64;
+
+    for _ in 0..options.loops {
+        for section in options.sections.iter() {
+            // copied so the UI can keep editing while the section renders
+            let Some(Ok(section)) = steps
+                .get(*section)
+                .map(|section| section.read().map(|section| section.clone()))
+            else {
+                warn!("section {section} doesn't exist, skipping it");
+                continue;
+            };
+
+            for step_i in 0..N_STEPS {
+                for pulse in 0..sixteenth_pulse {
+                    let frame = start + written as u64;
+                    let mut send_midi = |channel_i, event, frame| {
+                        engine.queue_midi(channel_i, frame, event);
+                    };
+
+                    if pulse == 0 {
+                        play_step(&mut send_midi, &section, step_i, &mut note_offs, frame);
+                    } else if pulse == sixteenth_pulse - 1 {
+                        stop_notes(&mut send_midi, &mut note_offs, frame);
+                    }
+
+                    clock += samples_per_pulse;
+                    let n_frames = clock.round() as usize - written;
+                    render_frames(engine, n_frames, &mut writer)?;
+                    written += n_frames;
+                }
+            }
+        }
+    }
+
+    stop_notes(
+        &mut |channel_i, event, frame| engine.queue_midi(channel_i, frame, event),
+        &mut note_offs,
+        start + written as u64,
+    );
+    let tail = (options.tail_secs.max(0.0) * config.sample_rate as f32) as usize;
+    render_frames(engine, tail, &mut writer)?;
+    written += tail;
+
+    writer.finalize()?;
+    info!("rendered {written} frames to {}", path.display());
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RenderOptions, render_to_wav};
+    use crate::{
+        N_SECTIONS,
+        catalog::PluginCatalog,
+        config::EngineConfig,
+        engine::MixerCommand,
+        master::{MasterSettings, Saturation},
+        mixer::Mixer,
+        mock::MockPlugin,
+        output::NullOutput,
+        step_sequencer::StepSequence,
+    };
+    use hound::WavReader;
+    use rack::prelude::MidiEvent;
+    use std::{
+        env,
+        sync::{Arc, RwLock},
+    };
+
+    #[test]
+    fn live_notes_stay_out_of_renders() {
+        // an output that never runs, the test pulls the buffers itself
+        let backend = NullOutput { buffers: Some(0) };
+        let (mut mixer, _dev) = Mixer::new(
+            EngineConfig::default(),
+            1,
+            &backend,
+            PluginCatalog::in_memory(),
+        );
+        mixer.send(MixerCommand::SetInstrument {
+            channel_i: 0,
+            sound_gen: Box::new(MockPlugin::new(1.0)),
+            health: Arc::default(),
+        });
+        mixer.set_master_settings(MasterSettings::new(Saturation::Off, false, 0.0));
+
+        // one note held down and another one queued to start later
+        let frame = mixer.engine.lock().unwrap().frame();
+        mixer.send_midi_at(0, [MidiEvent::note_on(60, 100, 0, 0)], frame);
+        let level = mixer.engine.lock().unwrap().process(64)[0][63];
+        assert!(level > 0.0);
+        mixer.send_midi_at(0, [MidiEvent::note_on(62, 100, 0, 0)], frame + 1000);
+
+        let steps: Vec<_> = (0..N_SECTIONS)
+            .map(|_| RwLock::new(vec![StepSequence::default()]))
+            .collect();
+        let options = RenderOptions {
+            sections: vec![0],
+            loops: 1,
+            bpm: 120,
+            tail_secs: 0.1,
+        };
+        let path = env::temp_dir().join("do-daw-live-notes-render.wav");
+        let written = render_to_wav(&mixer, &steps, &options, &path).unwrap();
+
+        let mut reader = WavReader::open(&path).unwrap();
+        let samples: Vec<f32> = reader.samples::<f32>().map(Result::unwrap).collect();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(samples.len(), written * 2);
+        assert!(samples.iter().all(|sample| *sample == 0.0));
+    }
+}
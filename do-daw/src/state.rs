@@ -0,0 +1,287 @@
+//! the saved internal state of plugins, so a sound survives swapping instruments or restarting.
+
+use crate::{
+    engine::{EffectChain, ParamTarget},
+    parameter::Parameter,
+    traits::Processor,
+};
+use log::*;
+use pyo3::prelude::*;
+
+/// what a serialized MixerState starts with.
+const MAGIC: &[u8; 4] = b"DDPS";
+const VERSION: u8 = 1;
+
+/// the internal state of one instrument or effect. for a VST3 that's the blob it hands out, for a
+/// native instrument or effect it's the values of its parameters.
+#[pyclass(get_all, from_py_object)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PluginState {
+    /// the name of the plugin the state came from, it's only restored to one of the same name
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+#[pymethods]
+impl PluginState {
+    #[new]
+    pub fn new(name: String, data: Vec<u8>) -> Self {
+        Self { name, data }
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "PluginState(name={:?}, {} bytes)",
+            self.name,
+            self.data.len()
+        )
+    }
+}
+
+impl PluginState {
+    /// the state of plugin, None if it has none to give. may take a while, so keep it off the
+    /// audio thread.
+    pub fn capture(plugin: &dyn Processor) -> Option<Self> {
+        Some(Self {
+            name: plugin.name().into(),
+            data: plugin.get_state()?,
+        })
+    }
+
+    /// restores this to plugin, which has to be of the same name as the one it came from, and
+    /// returns its parameters afterwards. keep it off the audio thread too.
+    pub fn restore(&self, plugin: &mut dyn Processor) -> Option<Vec<Parameter>> {
+        if plugin.name() != self.name {
+            warn!("not restoring the state of {} to {plugin}", self.name);
+            return None;
+        }
+
+        plugin.set_state(&self.data).then(|| plugin.parameters())
+    }
+}
+
+/// the states of every instrument and effect of a mixer. a slot with nothing in it, or whose
+/// plugin has no state to give, is None.
+#[pyclass(get_all, from_py_object)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MixerState {
+    /// the instrument of each channel
+    pub instruments: Vec<Option<PluginState>>,
+    /// the effects of each channel, in processing order
+    pub channel_effects: Vec<Vec<Option<PluginState>>>,
+    /// the effects of each aux bus
+    pub aux_effects: Vec<Vec<Option<PluginState>>>,
+    /// the master effects
+    pub master_effects: Vec<Option<PluginState>>,
+}
+
+#[pymethods]
+impl MixerState {
+    /// the states as bytes, for a project file or database.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+
+        write_slots(&mut out, &self.instruments);
+        write_chains(&mut out, &self.channel_effects);
+        write_chains(&mut out, &self.aux_effects);
+        write_slots(&mut out, &self.master_effects);
+
+        out
+    }
+
+    /// reads back what to_bytes wrote. None if bytes aren't a saved MixerState.
+    #[staticmethod]
+    pub fn from_bytes(bytes: Vec<u8>) -> Option<Self> {
+        let mut reader = Reader(&bytes);
+
+        if reader.take(MAGIC.len())? != MAGIC || reader.take(1)? != [VERSION] {
+            return None;
+        }
+
+        let state = Self {
+            instruments: reader.slots()?,
+            channel_effects: reader.chains()?,
+            aux_effects: reader.chains()?,
+            master_effects: reader.slots()?,
+        };
+
+        // trailing bytes mean it's not something we wrote
+        reader.0.is_empty().then_some(state)
+    }
+
+    pub fn __repr__(&self) -> String {
+        let saved = self
+            .instruments
+            .iter()
+            .chain(self.channel_effects.iter().flatten())
+            .chain(self.aux_effects.iter().flatten())
+            .chain(&self.master_effects)
+            .flatten()
+            .count();
+
+        format!("MixerState({saved} plugin states)")
+    }
+}
+
+impl MixerState {
+    /// every saved state and the slot it belongs in.
+    pub fn slots(&self) -> impl Iterator<Item = (ParamTarget, &PluginState)> {
+        let instruments = self
+            .instruments
+            .iter()
+            .enumerate()
+            .map(|(channel_i, state)| (ParamTarget::Instrument(channel_i), state));
+        let channel_effects =
+            self.channel_effects
+                .iter()
+                .enumerate()
+                .flat_map(move |(channel_i, slots)| {
+                    chain_slots(EffectChain::Channel(channel_i), slots)
+                });
+        let aux_effects = self
+            .aux_effects
+            .iter()
+            .enumerate()
+            .flat_map(move |(aux, slots)| chain_slots(EffectChain::Aux(aux), slots));
+
+        instruments
+            .chain(channel_effects)
+            .chain(aux_effects)
+            .chain(chain_slots(EffectChain::Master, &self.master_effects))
+            .filter_map(|(target, state)| Some((target, state.as_ref()?)))
+    }
+}
+
+/// the slots of an effect chain, paired with their states.
+fn chain_slots(
+    chain: EffectChain,
+    slots: &[Option<PluginState>],
+) -> impl Iterator<Item = (ParamTarget, &Option<PluginState>)> {
+    slots
+        .iter()
+        .enumerate()
+        .map(move |(effect, state)| (ParamTarget::Effect(chain, effect), state))
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_len(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+fn write_slots(out: &mut Vec<u8>, slots: &[Option<PluginState>]) {
+    write_len(out, slots.len());
+
+    for slot in slots {
+        match slot {
+            Some(state) => {
+                out.push(1);
+                write_bytes(out, state.name.as_bytes());
+                write_bytes(out, &state.data);
+            }
+            None => out.push(0),
+        }
+    }
+}
+
+fn write_chains(out: &mut Vec<u8>, chains: &[Vec<Option<PluginState>>]) {
+    write_len(out, chains.len());
+    chains.iter().for_each(|slots| write_slots(out, slots));
+}
+
+/// reads what the write_ functions wrote, front to back.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = self.0.split_at_checked(n)?;
+        self.0 = rest;
+
+        Some(taken)
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        let bytes = self.take(4)?.try_into().ok()?;
+
+        Some(u32::from_le_bytes(bytes) as usize)
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.len()?;
+
+        self.take(len)
+    }
+
+    fn slots(&mut self) -> Option<Vec<Option<PluginState>>> {
+        let len = self.len()?;
+        // a corrupt length shouldn't be able to reserve gigabytes
+        let mut slots = Vec::with_capacity(len.min(self.0.len()));
+
+        for _ in 0..len {
+            let slot = match self.take(1)? {
+                [0] => None,
+                [1] => Some(PluginState {
+                    name: String::from_utf8(self.bytes()?.to_vec()).ok()?,
+                    data: self.bytes()?.to_vec(),
+                }),
+                _ => return None,
+            };
+
+            slots.push(slot);
+        }
+
+        Some(slots)
+    }
+
+    fn chains(&mut self) -> Option<Vec<Vec<Option<PluginState>>>> {
+        let len = self.len()?;
+
+        (0..len).map(|_| self.slots()).collect()
+    }
+}
+
+/// parameter values as bytes, the state of the native instruments and effects.
+pub fn params_to_bytes(values: impl IntoIterator<Item = f32>) -> Vec<u8> {
+    values.into_iter().flat_map(f32::to_le_bytes).collect()
+}
+
+/// reads back what params_to_bytes wrote. None unless there are exactly n_params values.
+pub fn params_from_bytes(bytes: &[u8], n_params: usize) -> Option<Vec<f32>> {
+    if bytes.len() != n_params * 4 {
+        return None;
+    }
+
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| chunk.try_into().ok().map(f32::from_le_bytes))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MixerState, PluginState};
+
+    #[test]
+    fn mixer_state_round_trips() {
+        let synth = PluginState::new("Subtractive Synth".into(), vec![1, 2, 3, 4]);
+        let state = MixerState {
+            instruments: vec![Some(synth.clone()), None],
+            channel_effects: vec![vec![None, Some(synth.clone())], Vec::new()],
+            aux_effects: vec![Vec::new(); 2],
+            master_effects: vec![Some(PluginState::new("EQ".into(), Vec::new()))],
+        };
+        let bytes = state.to_bytes();
+
+        assert_eq!(MixerState::from_bytes(bytes.clone()), Some(state));
+        assert_eq!(
+            MixerState::from_bytes(bytes[..bytes.len() - 1].to_vec()),
+            None
+        );
+        assert_eq!(MixerState::from_bytes(b"not a state".to_vec()), None);
+    }
+}
@@ -1,20 +1,27 @@
 use crate::{
-    N_CHANNELS, N_SECTIONS, mixer::Mixer, step_sequencer::audio_wrapper::AudioOutputWrapper,
+    MAX_CHANNELS, N_SECTIONS,
+    mixer::Mixer,
+    output::{OutputBackend, OutputHandle},
+    render::{self, RenderOptions},
+    step_sequencer::audio_wrapper::AudioOutputWrapper,
 };
 use log::*;
 use pyo3::prelude::*;
 use rack::prelude::*;
 use std::{
+    collections::VecDeque,
+    path::PathBuf,
     sync::{
-        Arc, RwLock,
+        Arc, Mutex, PoisonError, RwLock,
         atomic::{AtomicBool, AtomicUsize, Ordering},
     },
     thread::{sleep, spawn},
     time::Duration,
 };
-use tinyaudio::OutputDevice;
 
 pub const N_STEPS: usize = 16;
+/// pulses per quarter note the sequencer runs at.
+pub const PPQ: usize = 48;
 
 pub mod audio_wrapper;
 
@@ -73,32 +80,41 @@ impl StepSequence {
     }
 }
 
+/// the sequences of every channel in every section, indexed [section][channel]. each section has
+/// one sequence per mixer channel.
+pub type StepGrid = Arc<[RwLock<Vec<StepSequence>>]>;
+
+/// the notes the sequencer has started and not yet stopped, and the channel each is on.
+type NoteOffs = Arc<Mutex<Vec<(u8, usize)>>>;
+
 #[pyclass]
 pub struct StepSequencer {
     pub mixer: Mixer,
-    pub steps: Arc<[Arc<[RwLock<StepSequence>]>]>,
+    pub steps: StepGrid,
     pub step_i: Arc<AtomicUsize>,
     pub section_i: Arc<AtomicUsize>,
     pub bpm: Arc<AtomicUsize>,
     pub playing: Arc<AtomicBool>,
+    /// shared with the sequencer thread so removing a channel can move the notes after it down
+    note_offs: NoteOffs,
 }
 
 impl StepSequencer {
-    pub fn new(mixer: Mixer, _device: OutputDevice) -> (Self, AudioOutputWrapper) {
+    pub fn new(
+        mixer: Mixer,
+        _device: OutputHandle,
+        backend: Box<dyn OutputBackend>,
+    ) -> (Self, AudioOutputWrapper) {
         let step_i: Arc<AtomicUsize> = Arc::new((N_STEPS - 1).into());
         let section_i: Arc<AtomicUsize> = Arc::new(0.into());
         let playing: Arc<AtomicBool> = Arc::new(false.into());
-        let steps: Vec<Arc<[RwLock<StepSequence>]>> = (0..N_SECTIONS)
-            .map(|_| {
-                let steps: Vec<RwLock<StepSequence>> = (0..N_CHANNELS)
-                    .map(|_| RwLock::new(StepSequence::default()))
-                    .collect();
-                steps.into()
-            })
+        let n_channels = mixer.get_n_channels();
+        let steps: StepGrid = (0..N_SECTIONS)
+            .map(|_| RwLock::new(vec![StepSequence::default(); n_channels]))
             .collect();
-        let steps: Arc<[Arc<[RwLock<StepSequence>]>]> = steps.into();
 
         let bpm: Arc<AtomicUsize> = Arc::new(99.into());
+        let note_offs: NoteOffs = Arc::new(Mutex::new(Vec::with_capacity(MAX_CHANNELS * 4)));
 
         let _jh = spawn({
             let mixer = mixer.clone();
@@ -107,9 +123,10 @@ impl StepSequencer {
             let playing = playing.clone();
             let section_i = section_i.clone();
             let bpm = bpm.clone();
+            let note_offs = note_offs.clone();
 
             move || {
-                do_run_sequence(mixer, steps, step_i, section_i, playing, bpm);
+                do_run_sequence(mixer, steps, step_i, section_i, playing, bpm, note_offs);
             }
         });
 
@@ -121,8 +138,13 @@ impl StepSequencer {
                 section_i,
                 bpm,
                 playing,
+                note_offs,
+            },
+            AudioOutputWrapper {
+                _device: Some(_device),
+                backend,
+                _jh,
             },
-            AudioOutputWrapper { _device, _jh },
         )
     }
 }
@@ -132,9 +154,9 @@ impl StepSequencer {
     /// sets the note at step of channel in section
     pub fn set_note(&mut self, channel_i: usize, step_i: usize, note: Option<u8>) -> bool {
         let section_i = self.section_i.load(Ordering::Relaxed);
-        let Some(Some(Ok(Some(new_note)))) = self.steps.get(section_i).map(|section| {
-            section.get(channel_i).map(|channel| {
-                channel.write().map(|mut channel| {
+        let Some(Ok(Some(new_note))) = self.steps.get(section_i).map(|section| {
+            section.write().map(|mut section| {
+                section.get_mut(channel_i).and_then(|channel| {
                     channel.steps.get_mut(step_i).map(|step| {
                         debug!("setting section {section_i}, channel {channel_i}, step {step_i}, to note {note:?}"); 
 
@@ -162,8 +184,8 @@ impl StepSequencer {
         let section_i = self.section_i.load(Ordering::Relaxed);
 
         self.steps.get(section_i).map(|section| {
-            section.get(channel_i).map(|channel| {
-                channel.write().map(|mut channel| {
+            section.write().map(|mut section| {
+                section.get_mut(channel_i).and_then(|channel| {
                     channel.steps.get_mut(step_i).map(|step| {
                         debug!("editing section {section_i}, channel {channel_i}, step {step_i}, by value {note:?}"); 
                         step.note.as_mut().map(|num| if note.abs() as u8 <= *num && !(note < 0 && *num == 24) && !step.mute {
@@ -200,10 +222,69 @@ impl StepSequencer {
         self.playing.load(Ordering::Relaxed)
     }
 
-    pub fn get_step_state(&self, channel_i: usize, step_i: usize) -> StepState {
+    /// the state of step_i of channel_i in the current section, None if there's no such step.
+    pub fn get_step_state(&self, channel_i: usize, step_i: usize) -> Option<StepState> {
         let section_i = self.section_i.load(Ordering::Relaxed);
+        let section = self.steps.get(section_i)?.read().ok()?;
+
+        section.get(channel_i)?.steps.get(step_i).copied()
+    }
+
+    /// adds a channel to the mixer, with an empty sequence in every section. returns its index,
+    /// or None if the mixer already has MAX_CHANNELS.
+    pub fn add_channel(&mut self) -> Option<usize> {
+        // holding every section keeps the sequencer from playing a step while the mixer and the
+        // grid disagree on the channels
+        let mut sections: Vec<_> = self
+            .steps
+            .iter()
+            .filter_map(|section| section.write().ok())
+            .collect();
+        let channel_i = self.mixer.add_channel()?;
+
+        sections
+            .iter_mut()
+            .for_each(|section| section.push(StepSequence::default()));
+        info!("added channel {channel_i}");
 
-        self.steps[section_i][channel_i].read().unwrap().steps[step_i]
+        Some(channel_i)
+    }
+
+    /// removes channel_i from the mixer along with its sequence in every section. the channels
+    /// after it move down by one. returns false if there's no such channel or it's the last one.
+    pub fn remove_channel(&mut self, channel_i: usize) -> bool {
+        let mut sections: Vec<_> = self
+            .steps
+            .iter()
+            .filter_map(|section| section.write().ok())
+            .collect();
+        // held until the channel is gone, so the sequencer can't stop a note on the old index of
+        // a channel that moved down
+        let mut note_offs = self
+            .note_offs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if !self.mixer.remove_channel(channel_i) {
+            return false;
+        }
+
+        // the removed channel's notes went with it
+        note_offs.retain(|(_, channel)| *channel != channel_i);
+        note_offs
+            .iter_mut()
+            .filter(|(_, channel)| *channel > channel_i)
+            .for_each(|(_, channel)| *channel -= 1);
+
+        for section in sections.iter_mut() {
+            if channel_i < section.len() {
+                section.remove(channel_i);
+            }
+        }
+
+        info!("removed channel {channel_i}");
+
+        true
     }
 
     pub fn get_section(&self) -> usize {
@@ -217,168 +298,353 @@ impl StepSequencer {
     pub fn set_bpm(&mut self, bpm: usize) {
         self.bpm.store(bpm, Ordering::Relaxed);
     }
+
+    /// bounces the sequence to a wav file at path. section picks the section to render, when it's
+    /// None every section with notes in it is rendered in order (the whole song). the sections are
+    /// played loops times, then rendering carries on for tail_secs so effect tails can ring out.
+    /// playback is stopped first and the live output is silent until rendering is done. returns
+    /// false if the render failed.
+    #[pyo3(signature = (path, section=None, loops=1, tail_secs=2.0))]
+    pub fn render_to_wav(
+        &mut self,
+        path: PathBuf,
+        section: Option<usize>,
+        loops: usize,
+        tail_secs: f32,
+    ) -> bool {
+        self.stop_playing();
+
+        let sections = match section {
+            Some(section_i) => vec![section_i],
+            None => (0..N_SECTIONS)
+                .filter(|section_i| {
+                    self.steps[*section_i].read().is_ok_and(|section| {
+                        section.iter().any(|channel| {
+                            channel
+                                .steps
+                                .iter()
+                                .any(|step| step.note.is_some() && !step.mute)
+                        })
+                    })
+                })
+                .collect(),
+        };
+        let options = RenderOptions {
+            sections,
+            loops,
+            bpm: self.get_bpm(),
+            tail_secs,
+        };
+
+        info!("rendering {options:?} to {}", path.display());
+
+        match render::render_to_wav(&self.mixer, &self.steps, &options, &path) {
+            Ok(_) => true,
+            Err(e) => {
+                error!("rendering to {} failed with error {e}", path.display());
+                false
+            }
+        }
+    }
 }
 
+/// plays the sequence while playing is set. the pulses are counted in frames of the engine's clock
+/// instead of slept out, so the tempo holds exactly however late the thread wakes up, and every
+/// note is stamped with the frame it should play at.
 fn do_run_sequence(
     mixer: Mixer,
-    steps: Arc<[Arc<[RwLock<StepSequence>]>]>,
+    steps: StepGrid,
     step_i: Arc<AtomicUsize>,
     section_i: Arc<AtomicUsize>,
     playing: Arc<AtomicBool>,
     bpm: Arc<AtomicUsize>,
+    note_offs: NoteOffs,
 ) {
-    let bpq = 48;
-    // beats_per_quater_note / (2 * <distance from a quarter>)
-    let sixteenth_pulse = bpq / 4;
-    // setup sync pulse time
-    let calc_wait_time = || {
-        Duration::from_secs_f64({
-            let bpm = bpm.load(Ordering::Relaxed) as f64;
-            let bpq = bpq as f64;
-
-            (60.0 / bpm) / bpq
-        })
-    };
-    trace!("pusle time = {}", calc_wait_time().as_secs_f64());
     let should_play = || playing.load(Ordering::Relaxed);
-    let mut note_offs: Vec<(u8, usize)> = Vec::with_capacity(N_CHANNELS * 4);
-    let mut pulses = 0;
-    let increment_step_i = || {
-        let tmp_step_i = step_i.load(Ordering::Relaxed);
-        step_i.store((tmp_step_i + 1) % N_STEPS, Ordering::Relaxed);
-        step_i.load(Ordering::Relaxed)
-    };
-    let mut stop_notes = {
-        let mut mixer = mixer.clone();
+    let mut playhead = Playhead::new(note_offs);
 
-        move |note_offs: &mut Vec<(u8, usize)>| {
-            for (note, channel_i) in note_offs.iter() {
-                trace!("stopping note: {note}");
-                mixer.stop_notes(vec![*note], *channel_i);
+    loop {
+        if should_play() {
+            let config = mixer.get_config();
+            // pulses are sent a buffer or two ahead of the audio, everything up to here is sent
+            // now
+            let horizon = mixer.clock.stamp() + config.buffer_frames as u64;
+            let samples_per_pulse =
+                samples_per_pulse(config.sample_rate, bpm.load(Ordering::Relaxed));
+
+            if !playhead.is_playing() {
+                let next_step = (step_i.load(Ordering::Relaxed) + 1) % N_STEPS;
+                playhead.start(next_step, mixer.clock.stamp());
             }
 
-            note_offs.clear();
-        }
-    };
+            playhead.send_until(&mixer, &steps, &section_i, horizon, samples_per_pulse);
 
-    loop {
-        if should_play() {
-            if pulses == 0 {
-                // happens first so that the step_i value is always the step thats playing
-                let i = increment_step_i();
-                trace!("playing step {i}");
-
-                // play notes from the current step.
-                for (channel_i, (mix_channel, steps)) in mixer
-                    .channels
-                    .iter()
-                    .zip(steps[section_i.load(Ordering::Relaxed)].iter())
-                    .enumerate()
-                {
-                    if let Ok(mut mix_channel) = mix_channel.write() {
-                        if let Ok(steps) = steps.read() {
-                            let step = steps.steps[i];
-                            trace!("step[{i}]: {step:?}");
-
-                            if let Some(sound_gen) = &mut mix_channel.sound_gen {
-                                let mut events = Vec::with_capacity(8);
-
-                                if let Some(note) = step.note {
-                                    events.push(MidiEvent::note_on(
-                                        note,
-                                        step.velocity,
-                                        step.channel,
-                                        0,
-                                    ));
-                                    trace!("playing note: {note}, on channel: {channel_i}");
-
-                                    note_offs.push((note, channel_i));
-                                }
-
-                                if step.pitch_bend != 0.0 {
-                                    let bend_amt =
-                                        step.pitch_bend * MidiEvent::PITCH_BEND_CENTER as f32;
-                                    let bend_amt = MidiEvent::PITCH_BEND_CENTER + bend_amt as u16;
-                                    let event = MidiEvent::pitch_bend(bend_amt, step.channel, 0);
-
-                                    events.push(event);
-                                }
-
-                                if step.mod_whl > 0.0 {
-                                    let mut value = (step.mod_whl * 127.0).round() as u8;
-
-                                    if value > 127 {
-                                        value = 127;
-                                    }
-
-                                    let event = MidiEvent::control_change(1, value, 0, 0);
-
-                                    events.push(event);
-                                }
-
-                                for ctrl in [step.macro_1, step.macro_2, step.macro_3, step.macro_4]
-                                {
-                                    if let Some((cc, val)) = ctrl {
-                                        let mut value = (val * 127.0).round() as u8;
-
-                                        if value > 127 {
-                                            value = 127;
-                                        }
-
-                                        let event = MidiEvent::control_change(cc, value, 0, 0);
-
-                                        events.push(event);
-                                    }
-                                }
-
-                                if !events.is_empty() {
-                                    if let Err(e) = sound_gen.send_midi(&events) {
-                                        error!("sending midi failed with error {e}");
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            } else if pulses == sixteenth_pulse - 1 {
-                stop_notes(&mut note_offs);
-            } else {
-                trace!("pulse count = {pulses}");
+            // step_i moves on when the engine gets to the step, so the UI shows the step that's
+            // being heard
+            if let Some(i) = playhead.heard(mixer.clock.now()) {
+                step_i.store(i, Ordering::Relaxed);
             }
 
-            pulses = (pulses + 1) % sixteenth_pulse;
-            sleep(calc_wait_time());
-        } else if !note_offs.is_empty() {
-            trace!("note_offs is not empty and stepper is not playing");
-            stop_notes(&mut note_offs);
+            sleep(Duration::from_secs_f64(
+                config.buffer_frames as f64 / config.sample_rate as f64 / 2.0,
+            ));
+        } else if playhead.is_playing() {
+            trace!("stepper stopped playing");
+            playhead.stop(&mixer, mixer.clock.stamp());
 
-            // reset step_i and pulses
+            // reset step_i
             step_i.store(0, Ordering::Relaxed);
-            pulses = 0;
         } else {
             // do nothing bc we want playback start to be super responsive
         }
     }
 }
 
+/// where the sequencer is in the sequence, and how far ahead of the engine it has sent it.
+struct Playhead {
+    /// the pulse of the current sixteenth
+    pulses: usize,
+    /// the frame of the engine's clock the next pulse falls on, None while stopped. kept as a
+    /// float so rounding errors don't add up into tempo drift
+    next_pulse: Option<f64>,
+    next_step: usize,
+    /// steps that have been sent to the mixer but haven't played yet, and the frames they play at
+    upcoming: VecDeque<(u64, usize)>,
+    /// the last frame anything was sent for, stopping never cuts in ahead of it
+    last_sent: u64,
+    note_offs: NoteOffs,
+}
+
+impl Playhead {
+    fn new(note_offs: NoteOffs) -> Self {
+        Self {
+            pulses: 0,
+            next_pulse: None,
+            next_step: 0,
+            upcoming: VecDeque::with_capacity(4),
+            last_sent: 0,
+            note_offs,
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        self.next_pulse.is_some()
+    }
+
+    /// starts playing from step, at frame of the engine's clock.
+    fn start(&mut self, step: usize, frame: u64) {
+        self.next_step = step;
+        self.next_pulse = Some(frame as f64);
+    }
+
+    /// sends every pulse that falls before horizon to the mixer, samples_per_pulse apart.
+    fn send_until(
+        &mut self,
+        mixer: &Mixer,
+        steps: &[RwLock<Vec<StepSequence>>],
+        section_i: &AtomicUsize,
+        horizon: u64,
+        samples_per_pulse: f64,
+    ) {
+        let sixteenth_pulse = PPQ / 4;
+        let Some(pulse_frame) = self.next_pulse.as_mut() else {
+            return;
+        };
+
+        while (pulse_frame.round() as u64) < horizon {
+            let frame = pulse_frame.round() as u64;
+
+            if self.pulses == 0 {
+                let i = self.next_step;
+                self.next_step = (i + 1) % N_STEPS;
+                trace!("playing step {i} at frame {frame}");
+
+                // play notes from the current step. the note offs are locked after the section,
+                // like remove_channel does
+                if let Some(Ok(section)) = steps
+                    .get(section_i.load(Ordering::Relaxed))
+                    .map(|section| section.read())
+                {
+                    let mut note_offs = self
+                        .note_offs
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner);
+                    play_step(&mut live(mixer), &section, i, &mut note_offs, frame);
+                }
+
+                self.upcoming.push_back((frame, i));
+            } else if self.pulses == sixteenth_pulse - 1 {
+                let mut note_offs = self
+                    .note_offs
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                stop_notes(&mut live(mixer), &mut note_offs, frame);
+            }
+
+            self.last_sent = frame;
+            self.pulses = (self.pulses + 1) % sixteenth_pulse;
+            *pulse_frame += samples_per_pulse;
+        }
+    }
+
+    /// the last of the steps sent that the engine has got to by now, if it got to any.
+    fn heard(&mut self, now: u64) -> Option<usize> {
+        let mut heard = None;
+
+        while let Some(&(frame, i)) = self.upcoming.front()
+            && frame <= now
+        {
+            heard = Some(i);
+            self.upcoming.pop_front();
+        }
+
+        heard
+    }
+
+    /// stops the notes that are playing, no earlier than frame, and rewinds.
+    fn stop(&mut self, mixer: &Mixer, frame: u64) {
+        let mut note_offs = self
+            .note_offs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        stop_notes(&mut live(mixer), &mut note_offs, frame.max(self.last_sent));
+
+        self.pulses = 0;
+        self.next_pulse = None;
+        self.upcoming.clear();
+    }
+}
+
+/// how many frames of audio one sequencer pulse lasts at bpm.
+pub(crate) fn samples_per_pulse(sample_rate: usize, bpm: usize) -> f64 {
+    (sample_rate as f64 * 60.0) / (bpm.max(1) as f64 * PPQ as f64)
+}
+
+/// sends midi to the mixer, for play_step and stop_notes while the sequencer plays live.
+fn live(mixer: &Mixer) -> impl FnMut(usize, MidiEvent, u64) + '_ {
+    |channel_i, event, frame| mixer.send_midi_at(channel_i, [event], frame)
+}
+
+/// sends the notes, bends and CCs of step i of every channel in section through send_midi, to
+/// play at frame of the engine's clock. the notes started are pushed to note_offs so they can be
+/// stopped later.
+pub(crate) fn play_step(
+    send_midi: &mut impl FnMut(usize, MidiEvent, u64),
+    section: &[StepSequence],
+    i: usize,
+    note_offs: &mut Vec<(u8, usize)>,
+    frame: u64,
+) {
+    for (channel_i, steps) in section.iter().enumerate() {
+        let step = steps.steps[i];
+        trace!("step[{i}]: {step:?}");
+
+        let mut events = Vec::with_capacity(8);
+
+        if let Some(note) = step.note {
+            events.push(MidiEvent::note_on(note, step.velocity, step.channel, 0));
+            trace!("playing note: {note}, on channel: {channel_i}");
+
+            note_offs.push((note, channel_i));
+        }
+
+        if step.pitch_bend != 0.0 {
+            let bend_amt = step.pitch_bend * MidiEvent::PITCH_BEND_CENTER as f32;
+            let bend_amt = MidiEvent::PITCH_BEND_CENTER + bend_amt as u16;
+            let event = MidiEvent::pitch_bend(bend_amt, step.channel, 0);
+
+            events.push(event);
+        }
+
+        if step.mod_whl > 0.0 {
+            let mut value = (step.mod_whl * 127.0).round() as u8;
+
+            if value > 127 {
+                value = 127;
+            }
+
+            let event = MidiEvent::control_change(1, value, 0, 0);
+
+            events.push(event);
+        }
+
+        for ctrl in [step.macro_1, step.macro_2, step.macro_3, step.macro_4] {
+            if let Some((cc, val)) = ctrl {
+                let mut value = (val * 127.0).round() as u8;
+
+                if value > 127 {
+                    value = 127;
+                }
+
+                let event = MidiEvent::control_change(cc, value, 0, 0);
+
+                events.push(event);
+            }
+        }
+
+        for event in events {
+            send_midi(channel_i, event, frame);
+        }
+    }
+}
+
+/// stops every note in note_offs through send_midi at frame of the engine's clock and clears it.
+pub(crate) fn stop_notes(
+    send_midi: &mut impl FnMut(usize, MidiEvent, u64),
+    note_offs: &mut Vec<(u8, usize)>,
+    frame: u64,
+) {
+    for (note, channel_i) in note_offs.iter() {
+        trace!("stopping note: {note}");
+        send_midi(*channel_i, MidiEvent::note_off(*note, 100, 0, 0), frame);
+    }
+
+    note_offs.clear();
+}
+
 #[cfg(test)]
 mod test {
-    use std::{thread::sleep, time::Duration};
+    use std::{
+        sync::{Arc, RwLock, atomic::AtomicUsize},
+        thread::sleep,
+        time::Duration,
+    };
 
     use rack::*;
 
-    use crate::{N_CHANNELS, mixer::Mixer, step_sequencer::StepSequencer};
+    use crate::{
+        N_CHANNELS, N_SECTIONS,
+        catalog::PluginCatalog,
+        config::EngineConfig,
+        engine::MixerCommand,
+        instruments::NativeInstrument,
+        master::{MasterSettings, Saturation},
+        mixer::Mixer,
+        mock::MockPlugin,
+        output::{DeviceOutput, NullOutput, OutputBackend},
+        step_sequencer::{
+            N_STEPS, PPQ, Playhead, StepGrid, StepSequence, StepSequencer, samples_per_pulse,
+        },
+    };
 
     #[test]
     fn audio_ouptut() {
         // env_logger::builder().format_timestamp(None).init();
 
-        let (mixer, dev) = Mixer::new();
-        let (mut seq, _audio_wrapper) = StepSequencer::new(mixer, dev);
+        let backend: Box<dyn OutputBackend> = Box::new(DeviceOutput);
+        let (mixer, dev) = Mixer::new(
+            EngineConfig::default(),
+            N_CHANNELS,
+            backend.as_ref(),
+            PluginCatalog::in_memory(),
+        );
+        let (mut seq, _audio_wrapper) = StepSequencer::new(mixer, dev, backend);
         let chan = 0;
 
+        // a native instrument, so the test doesn't depend on what plugins are installed
         for chan in 0..N_CHANNELS {
-            seq.mixer.set_instrument(chan, "Wt Synth".into());
+            let synth = NativeInstrument::Subtractive.processor(seq.mixer.get_config());
+            seq.mixer.set_processor(chan, synth);
         }
 
         let on_events = vec![
@@ -388,24 +654,115 @@ mod test {
         ];
         // info!("instrument loaded");
 
-        if let Ok(mut channel) = seq.mixer.channels[chan].write() {
-            if let Some(sound_gen) = &mut channel.sound_gen {
-                if let Err(e) = sound_gen.send_midi(&on_events) {
-                    panic!("sending midi failed with error {e}");
-                }
-            } else {
-                panic!("no sound generator");
-            }
-        } else {
-            panic!("failed to write channel {chan}");
+        if seq.mixer.get_plugin_names()[chan].is_none() {
+            panic!("no sound generator");
         }
 
+        seq.mixer.send_midi(chan, on_events);
+
         sleep(Duration::from_secs(5));
 
-        if let Some(plugin) = &seq.mixer.channels[chan].read().unwrap().sound_gen {
-            log::debug!("sound_gen = {:?}", plugin.info().name.clone());
-        }
+        log::debug!("sound_gen = {:?}", seq.mixer.get_plugin_names()[chan]);
 
         // panic!("foobar");
     }
+
+    #[test]
+    fn removing_a_channel_moves_the_ones_after_it() {
+        let backend: Box<dyn OutputBackend> = Box::new(NullOutput::default());
+        let (mut mixer, dev) = Mixer::new(
+            EngineConfig::default(),
+            3,
+            backend.as_ref(),
+            PluginCatalog::in_memory(),
+        );
+        mixer.set_usb_midi_target(2);
+        let (mut seq, _audio_wrapper) = StepSequencer::new(mixer, dev, backend);
+        *seq.note_offs.lock().unwrap() = vec![(60, 0), (61, 1), (62, 2)];
+
+        assert!(seq.remove_channel(1));
+        assert_eq!(*seq.note_offs.lock().unwrap(), vec![(60, 0), (62, 1)]);
+        assert_eq!(seq.mixer.get_usb_midi_target(), 1);
+
+        assert!(seq.get_step_state(1, 0).is_some());
+        assert_eq!(seq.get_step_state(2, 0), None);
+        assert_eq!(seq.get_step_state(0, N_STEPS), None);
+    }
+
+    #[test]
+    fn steps_play_on_the_frames_they_were_stamped_with() {
+        // an output that never runs, the test pulls the buffers itself
+        let backend = NullOutput { buffers: Some(0) };
+        let (mut mixer, _dev) = Mixer::new(
+            EngineConfig::default(),
+            1,
+            &backend,
+            PluginCatalog::in_memory(),
+        );
+        mixer.send(MixerCommand::SetInstrument {
+            channel_i: 0,
+            sound_gen: Box::new(MockPlugin::new(1.0)),
+            health: Arc::default(),
+        });
+        // without the limiter's lookahead the notes start on the frame they're sent for
+        mixer.set_master_settings(MasterSettings::new(Saturation::Off, false, 0.0));
+
+        let steps: StepGrid = (0..N_SECTIONS)
+            .map(|_| RwLock::new(vec![StepSequence::default()]))
+            .collect();
+        steps[0].write().unwrap()[0]
+            .steps
+            .iter_mut()
+            .for_each(|step| step.note = Some(60));
+        let section_i = AtomicUsize::new(0);
+        let config = mixer.get_config();
+        let buffer_frames = config.buffer_frames;
+        let mut engine = mixer.engine.lock().unwrap();
+        let mut playhead = Playhead::new(Arc::default());
+        let start = engine.frame() + buffer_frames as u64;
+        playhead.start(0, start);
+
+        let mut onsets = Vec::new();
+        let mut last = 0.0;
+
+        // half a bar at 120 bpm, then half a bar at 150
+        for bpm in [120, 150] {
+            let samples_per_pulse = samples_per_pulse(config.sample_rate, bpm);
+            let step_frames = samples_per_pulse * (PPQ / 4) as f64;
+            let n_buffers = (8.0 * step_frames / buffer_frames as f64) as usize;
+
+            for _ in 0..n_buffers {
+                // sent a buffer ahead, like the sequencer thread does
+                let horizon = engine.frame() + 2 * buffer_frames as u64;
+                playhead.send_until(&mixer, &steps, &section_i, horizon, samples_per_pulse);
+
+                let buffer_start = engine.frame();
+                let [left, _] = engine.process(buffer_frames);
+
+                for (i, sample) in left.iter().enumerate() {
+                    if *sample > 0.0 && last == 0.0 {
+                        onsets.push(buffer_start + i as u64);
+                    }
+
+                    last = *sample;
+                }
+            }
+        }
+
+        // 500 frames a pulse at 120 bpm, 400 at 150. the step the tempo changed in is in between
+        let spacings: Vec<u64> = onsets.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        let changed = spacings
+            .iter()
+            .position(|spacing| *spacing != 6000)
+            .unwrap();
+        assert_eq!(onsets[0], start);
+        assert!(changed >= 7, "{spacings:?}");
+        assert!(spacings.len() - changed > 4, "{spacings:?}");
+        assert!(
+            spacings[changed + 1..]
+                .iter()
+                .all(|spacing| *spacing == 4800),
+            "{spacings:?}"
+        );
+    }
 }
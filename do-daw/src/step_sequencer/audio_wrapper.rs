@@ -1,9 +1,51 @@
+use crate::{
+    config::EngineConfig,
+    mixer::Mixer,
+    output::{OutputBackend, OutputHandle},
+};
+use log::*;
 use pyo3::prelude::*;
 use std::thread::JoinHandle;
-use tinyaudio::OutputDevice;
 
 #[pyclass(unsendable)]
 pub struct AudioOutputWrapper {
-    pub _device: OutputDevice,
+    /// None only while the device is being restarted
+    pub _device: Option<OutputHandle>,
+    /// what _device was started with, used to restart it
+    pub backend: Box<dyn OutputBackend>,
     pub _jh: JoinHandle<()>,
 }
+
+#[pymethods]
+impl AudioOutputWrapper {
+    /// restarts the output device with a new sample rate and buffer size and re-initializes every
+    /// loaded plugin to match. a wav output goes on in a new numbered file, see WavOutput.path_for.
+    /// returns false if the new device couldn't be started.
+    pub fn reconfigure(&mut self, mixer: &Mixer, config: EngineConfig) -> bool {
+        {
+            let Ok(mut engine) = mixer.engine.lock() else {
+                error!("the mixer engine is poisoned, can't reconfigure it");
+                return false;
+            };
+
+            // the old device only ever try_locks the engine, so it can't block on us here
+            self._device = None;
+            engine.reconfigure(config);
+
+            if let Ok(mut mixer_config) = mixer.config.write() {
+                *mixer_config = config;
+            }
+        }
+
+        match self.backend.start(mixer.engine.clone(), config) {
+            Ok(device) => {
+                self._device = Some(device);
+                true
+            }
+            Err(e) => {
+                error!("failed to restart the audio output with {config:?}: {e}");
+                false
+            }
+        }
+    }
+}
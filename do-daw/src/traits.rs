@@ -1,9 +1,113 @@
-use crate::Sample;
+use crate::{Sample, config::EngineConfig, parameter::Parameter};
+use rack::prelude::MidiEvent;
+use std::fmt::Display;
 
-pub trait GenSamples {
+/// anything that can sit in a plugin chain, as its instrument or as an effect. VST3 plugins,
+/// the native instruments and effects, and the tests' mock plugins all are. Display says what it
+/// is and where it came from, for the logs.
+pub trait Processor: Display + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// the categories it files itself under, e.g. "Drum".
+    fn categories(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// called when it's loaded and whenever the engine's config changes. may allocate.
+    fn initialize(&mut self, config: EngineConfig);
+
+    /// processes num_frames of inputs into outputs. an instrument gets no inputs, an effect gets
+    /// a stereo pair followed by the sidechain's pair when its slot has one. outputs is a stereo
+    /// pair. returns false if it failed.
+    fn process(
+        &mut self,
+        inputs: &[&[Sample]],
+        outputs: &mut [&mut [Sample]],
+        num_frames: usize,
+    ) -> bool;
+
+    /// plays, stops or bends notes. called between buffers, the event's sample_offset is where in
+    /// the next process call it falls. effects can ignore it.
+    fn send_midi(&mut self, _event: MidiEvent) {}
+
+    /// every parameter and its current value. allocates, so keep it off the audio thread.
+    fn parameters(&self) -> Vec<Parameter> {
+        Vec::new()
+    }
+
+    /// sets a parameter. returns false if there's no such parameter or it couldn't be set.
+    fn set_parameter(&mut self, _index: usize, _value: f32) -> bool {
+        false
+    }
+
+    /// its full internal state, to be handed back to set_state later. None if it has none or it
+    /// couldn't be read. may allocate.
+    fn get_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// restores a state from get_state. returns false if it couldn't. may allocate.
+    fn set_state(&mut self, _state: &[u8]) -> bool {
+        false
+    }
+}
+
+/// a sound source that runs natively instead of as a plugin.
+pub trait GenSamples: Send + Sync {
     // /// create a single mono sample
     // fn get_sample(&mut self) -> Sample;
 
     /// fill and output buffer
     fn fill_mono_buffer(&mut self, output_buffer: &mut [Sample], buffer_size: usize);
+
+    /// plays, stops or bends notes. called between buffers.
+    fn handle_midi(&mut self, event: &MidiEvent);
+
+    /// called when the instrument is loaded and whenever the engine's sample rate changes.
+    fn set_sample_rate(&mut self, sample_rate: usize);
+
+    fn params(&self) -> &'static [ParamInfo];
+
+    fn get_parameter(&self, index: usize) -> Option<f32>;
+
+    /// sets a parameter, clamped to its range. returns false if there's no such parameter.
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool;
+}
+
+/// a parameter of a native instrument or effect, in the same terms as a plugin's rack::ParameterInfo.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParamInfo {
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub unit: &'static str,
+}
+
+/// an effect that runs natively instead of as a plugin. process and the parameter methods work
+/// like their namesakes on rack's PluginInstance, except parameters are in their own units
+/// instead of normalized.
+pub trait ProcessSamples: Send + Sync {
+    /// processes num_frames of inputs into outputs. inputs are a stereo pair, followed by the
+    /// sidechain's pair when the slot has one. outputs is a stereo pair.
+    fn process(&mut self, inputs: &[&[Sample]], outputs: &mut [&mut [Sample]], num_frames: usize);
+
+    /// called when the effect is loaded and whenever the engine's sample rate changes. may
+    /// allocate.
+    fn set_sample_rate(&mut self, sample_rate: usize);
+
+    fn params(&self) -> &'static [ParamInfo];
+
+    fn get_parameter(&self, index: usize) -> Option<f32>;
+
+    /// sets a parameter, clamped to its range. returns false if there's no such parameter.
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool;
+
+    fn parameter_count(&self) -> usize {
+        self.params().len()
+    }
+
+    fn parameter_info(&self, index: usize) -> Option<ParamInfo> {
+        self.params().get(index).copied()
+    }
 }
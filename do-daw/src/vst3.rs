@@ -0,0 +1,115 @@
+//! VST3 plugins, loaded through rack.
+
+use crate::{Sample, config::EngineConfig, parameter::Parameter, traits::Processor};
+use log::*;
+use rack::{prelude::*, vst3::Vst3Plugin};
+use std::fmt::{self, Display};
+
+/// a loaded VST3 plugin.
+pub struct Vst3 {
+    plugin: Vst3Plugin,
+}
+
+impl Vst3 {
+    pub fn new(plugin: Vst3Plugin) -> Self {
+        Self { plugin }
+    }
+
+    pub fn info(&self) -> &PluginInfo {
+        self.plugin.info()
+    }
+}
+
+impl Processor for Vst3 {
+    fn name(&self) -> &str {
+        &self.plugin.info().name
+    }
+
+    fn categories(&self) -> Vec<String> {
+        self.plugin.get_categories()
+    }
+
+    fn initialize(&mut self, config: EngineConfig) {
+        if let Err(e) = self
+            .plugin
+            .initialize(config.sample_rate as f64, config.buffer_frames)
+        {
+            warn!("plugin failed to init. {e}");
+        } else {
+            info!("inited plugin: {}", self.name());
+        }
+    }
+
+    fn process(
+        &mut self,
+        inputs: &[&[Sample]],
+        outputs: &mut [&mut [Sample]],
+        num_frames: usize,
+    ) -> bool {
+        self.plugin.process(inputs, outputs, num_frames).is_ok()
+    }
+
+    fn send_midi(&mut self, event: MidiEvent) {
+        if let Err(e) = self.plugin.send_midi(&[event]) {
+            error!("sending midi failed with error {e}");
+        }
+    }
+
+    /// values are normalized, the way rack hands them over.
+    fn parameters(&self) -> Vec<Parameter> {
+        (0..self.plugin.parameter_count())
+            .filter_map(|i| match self.plugin.parameter_info(i) {
+                Ok(info) => Some(Parameter {
+                    index: i,
+                    value: self.plugin.get_parameter(i).unwrap_or(info.default),
+                    name: info.name,
+                    min: info.min,
+                    max: info.max,
+                    default: info.default,
+                    unit: info.unit,
+                    normalized: true,
+                }),
+                Err(e) => {
+                    warn!(
+                        "couldn't get info for parameter {i} of {}. {e}",
+                        self.name()
+                    );
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> bool {
+        if let Err(e) = self.plugin.set_parameter(index, value) {
+            warn!("setting parameter {index} of {} failed. {e}", self.name());
+
+            return false;
+        }
+
+        true
+    }
+
+    fn get_state(&self) -> Option<Vec<u8>> {
+        self.plugin
+            .get_state()
+            .inspect_err(|e| warn!("couldn't get the state of {}. {e}", self.name()))
+            .ok()
+    }
+
+    fn set_state(&mut self, state: &[u8]) -> bool {
+        if let Err(e) = self.plugin.set_state(state) {
+            warn!("couldn't restore the state of {}. {e}", self.name());
+
+            return false;
+        }
+
+        true
+    }
+}
+
+impl Display for Vst3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "plugin @ path {}", self.info().path.display())
+    }
+}
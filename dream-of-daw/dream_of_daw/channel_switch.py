@@ -1,16 +1,15 @@
 import pygame
 from .config import *
-from do_daw import N_CHANNELS
 from do_daw import UiSector
 
 
-def draw_channel_button(i, font, color, text):
+def draw_channel_button(i, n_channels, font, color, text):
     mid_y = STEP_BUTTON_BOUNDING_BOX.top + \
-        ((STEP_BUTTON_BOUNDING_BOX.height / (N_CHANNELS + 1)) * (i + 1))
+        ((STEP_BUTTON_BOUNDING_BOX.height / (n_channels + 1)) * (i + 1))
     center = (CHANNEL_MID_X, mid_y)
     button_w = STEP_BUTTON_BOUNDING_BOX.left - SIDE_BARS_W
     button = pygame.Rect(center[0], center[1], button_w * 0.75,
-                         (STEP_BUTTON_BOUNDING_BOX.height / (N_CHANNELS + 1)) * 0.8)
+                         (STEP_BUTTON_BOUNDING_BOX.height / (n_channels + 1)) * 0.8)
     button.center = center
     pygame.draw.rect(screen, color, button,
                      BUTTON_BOARDER_W, border_radius=BOARDER_RADIUS)
@@ -24,7 +23,9 @@ def draw_channel_button(i, font, color, text):
 def draw_channel_switcher(font, channel_i, plugins):
     in_sector = cursor.sector == UiSector.ChannelSelect
 
-    for i in range(N_CHANNELS):
+    n_channels = len(plugins)
+
+    for i in range(n_channels):
         color = SURFACE_0
 
         if channel_i == i:
@@ -33,4 +34,46 @@ def draw_channel_switcher(font, channel_i, plugins):
         if in_sector and cursor.index == i:
             color = GREEN
 
-        draw_channel_button(i, font, color, plugins[i])
+        draw_channel_button(i, n_channels, font, color, plugins[i])
+
+
+def draw_channel_meter(i, n_channels, reading):
+    """draws a left/right level bar pair, for channel i, in the gap left of its channel button."""
+    slot_h = STEP_BUTTON_BOUNDING_BOX.height / (n_channels + 1)
+    mid_y = STEP_BUTTON_BOUNDING_BOX.top + (slot_h * (i + 1))
+    bar_h = slot_h * 0.8
+    bar_w = SIDE_BARS_W * 0.04
+    bottom = mid_y + bar_h / 2
+
+    for side, x in enumerate((SIDE_BARS_W * 1.02, SIDE_BARS_W * 1.02 + bar_w * 1.5)):
+        level = min(reading.peak[side], 1.0)
+        hold = min(reading.peak_hold[side], 1.0)
+        color = RED if reading.clip[side] else GREEN
+
+        pygame.draw.rect(screen, SURFACE_0, pygame.Rect(
+            x, bottom - bar_h, bar_w, bar_h))
+        pygame.draw.rect(screen, color, pygame.Rect(
+            x, bottom - bar_h * level, bar_w, bar_h * level))
+        pygame.draw.line(screen, YELLOW, (x, bottom - bar_h * hold),
+                         (x + bar_w, bottom - bar_h * hold))
+
+
+def draw_channel_meters(meters):
+    for i in range(len(meters)):
+        draw_channel_meter(i, len(meters), meters[i])
+
+
+def draw_gain_reduction(gain_reduction, n_channels, full_scale_db=12.0):
+    """draws how far the master saturation/limiter is turning the mix down, as a bar hanging from
+    the top of the empty slot above the channel meters."""
+    slot_h = STEP_BUTTON_BOUNDING_BOX.height / (n_channels + 1)
+    mid_y = STEP_BUTTON_BOUNDING_BOX.top + (slot_h * 0.5)
+    bar_h = slot_h * 0.8
+    bar_w = SIDE_BARS_W * 0.04 * 2.5
+    top = mid_y - bar_h / 2
+    amount = min(gain_reduction / full_scale_db, 1.0)
+
+    pygame.draw.rect(screen, SURFACE_0, pygame.Rect(
+        SIDE_BARS_W * 1.02, top, bar_w, bar_h))
+    pygame.draw.rect(screen, PEACH, pygame.Rect(
+        SIDE_BARS_W * 1.02, top, bar_w, bar_h * amount))